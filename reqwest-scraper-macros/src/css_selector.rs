@@ -1,5 +1,9 @@
-use crate::utils::syn::{get_type_detail, PathType};
-use darling::{ast::Data, util::Flag, FromDeriveInput, FromField, FromMeta};
+use crate::utils::syn::{get_generic_type, get_type_detail, PathType};
+use darling::{
+    ast::{Data, Fields},
+    util::Flag,
+    FromDeriveInput, FromField, FromMeta, FromVariant,
+};
 use proc_macro2::{Span, TokenStream};
 use quote::quote;
 use scraper::Selector;
@@ -10,12 +14,21 @@ use syn::{spanned::Spanned, DeriveInput, Error, Result};
 struct CssSelector(String);
 
 #[derive(Debug, FromDeriveInput)]
-#[darling(attributes(selector), supports(struct_named))]
+#[darling(attributes(selector), supports(struct_named, enum_named, enum_unit))]
 struct CssSelectorScraper {
     ident: syn::Ident,
     generics: syn::Generics,
-    data: Data<(), CssSelectorStructField>,
+    data: Data<CssSelectorVariant, CssSelectorStructField>,
     path: Option<CssSelector>,
+    skip_unmatched: Flag,
+}
+
+#[derive(Debug, FromVariant)]
+#[darling(attributes(selector))]
+struct CssSelectorVariant {
+    ident: syn::Ident,
+    fields: Fields<CssSelectorStructField>,
+    when: Option<CssSelector>,
 }
 
 #[derive(Debug, FromField)]
@@ -23,7 +36,7 @@ struct CssSelectorScraper {
 struct CssSelectorStructField {
     ident: Option<syn::Ident>,
     ty: syn::Type,
-    path: Option<String>,
+    path: Option<CssSelector>,
     default: Option<String>,
     name: Flag,
     id: Flag,
@@ -33,37 +46,50 @@ struct CssSelectorStructField {
     has_class: Option<String>,
     attr: Option<String>,
     map: Option<syn::Path>,
+    nested: Flag,
 }
 
 pub fn expand_derive_from_response(input: DeriveInput) -> syn::Result<TokenStream> {
     let scraper = CssSelectorScraper::from_derive_input(&input)?;
+    let skip_unmatched = check_skip_unmatched(&scraper)?;
 
     let type_name = scraper.ident;
     let generics = scraper.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    let fields = scraper
-        .data
-        .as_ref()
-        .take_struct()
-        .ok_or_else(|| Error::new(input.span(), "css selector should never be used on enum"))?
-        .fields;
-
-    let field_extractors = generate_field_extractors(fields)?;
+    let item_selector = scraper.path.as_ref();
+    let element_constructor = generate_constructor(
+        &type_name,
+        &scraper.data,
+        item_selector,
+        ExtractContext::Element,
+        skip_unmatched,
+    )?;
 
     Ok(match scraper.path {
         Some(selector) => {
+            let compiled = selector.compiled();
             quote! {
                 impl #impl_generics ::reqwest_scraper::FromCssSelector for #type_name #ty_generics #where_clause {
                     type CssSelectorExtractResult = ::reqwest_scraper::error::Result<std::vec::Vec<Self>>;
                     fn from_html(html: ::reqwest_scraper::css_selector::Html) -> Self::CssSelectorExtractResult {
-                        let list = html.select(#selector)?;
+                        let list = html.select(#compiled)?;
+                        let mut result: Vec<Self> = std::vec::Vec::new();
+
+                        for item in list.iter() {
+                            let extract_item = #element_constructor;
+                            result.push(extract_item);
+                        }
+
+                        Ok(result)
+                    }
+
+                    fn from_select_item(item: &::reqwest_scraper::css_selector::SelectItem) -> Self::CssSelectorExtractResult {
+                        let list = item.select(#compiled)?;
                         let mut result: Vec<Self> = std::vec::Vec::new();
 
                         for item in list.iter() {
-                            let extract_item = Self {
-                                #(#field_extractors),*
-                            };
+                            let extract_item = #element_constructor;
                             result.push(extract_item);
                         }
 
@@ -72,24 +98,178 @@ pub fn expand_derive_from_response(input: DeriveInput) -> syn::Result<TokenStrea
                 }
             }
         }
-        None => quote! {
-            impl #impl_generics ::reqwest_scraper::FromCssSelector for #type_name #ty_generics #where_clause {
-                type CssSelectorExtractResult = ::reqwest_scraper::error::Result<Self>;
-                fn from_html(html: ::reqwest_scraper::css_selector::Html) -> Self::CssSelectorExtractResult {
-                    let item = &html;
-
-                    Ok(Self {
-                        #(#field_extractors),*
-                    })
+        None => {
+            let html_constructor = generate_constructor(
+                &type_name,
+                &scraper.data,
+                item_selector,
+                ExtractContext::Html,
+                false,
+            )?;
+            quote! {
+                impl #impl_generics ::reqwest_scraper::FromCssSelector for #type_name #ty_generics #where_clause {
+                    type CssSelectorExtractResult = ::reqwest_scraper::error::Result<Self>;
+                    fn from_html(html: ::reqwest_scraper::css_selector::Html) -> Self::CssSelectorExtractResult {
+                        let item = &html;
+
+                        Ok(#html_constructor)
+                    }
+
+                    fn from_select_item(item: &::reqwest_scraper::css_selector::SelectItem) -> Self::CssSelectorExtractResult {
+                        let item = *item;
+
+                        Ok(#element_constructor)
+                    }
                 }
             }
-        },
+        }
+    })
+}
+
+/// `skip_unmatched` only applies to the items of a list of enums
+fn check_skip_unmatched(scraper: &CssSelectorScraper) -> Result<bool> {
+    let flag = &scraper.skip_unmatched;
+    if flag.is_present() && !scraper.data.is_enum() {
+        return Err(Error::new(
+            flag.span(),
+            "`skip_unmatched` is only supported on enums",
+        ));
+    }
+    if flag.is_present() && scraper.path.is_none() {
+        return Err(Error::new(
+            flag.span(),
+            "`skip_unmatched` requires the `path` selector of the list items",
+        ));
+    }
+    Ok(flag.is_present())
+}
+
+/// What `item` refers to in the generated code
+#[derive(Clone, Copy)]
+enum ExtractContext {
+    /// `item` is the whole `Html` document
+    Html,
+    /// `item` is a `SelectItem`
+    Element,
+}
+
+impl ExtractContext {
+    /// the element that fields without a selector path are extracted from
+    fn root(self) -> TokenStream {
+        match self {
+            Self::Html => quote! {item.root_element()},
+            Self::Element => quote! {item},
+        }
+    }
+
+    /// test whether the `when` selector of an enum variant matches the item
+    fn matches(self, when: &CssSelector) -> TokenStream {
+        let when = when.compiled();
+        match self {
+            Self::Html => quote! {item.select(#when)?.first().is_some()},
+            Self::Element => {
+                quote! {{
+                    let when = #when;
+                    item.matches(when)? || item.select(when)?.first().is_some()
+                }}
+            }
+        }
+    }
+}
+
+/// Generate the expression that constructs `Self` from `item`.
+///
+/// For enums, the first variant whose `when` selector matches is constructed,
+/// a variant without `when` always matches.
+/// With `skip_unmatched`, an item of the list that no variant matches is skipped, rather than an error.
+fn generate_constructor(
+    type_name: &syn::Ident,
+    data: &Data<CssSelectorVariant, CssSelectorStructField>,
+    item_selector: Option<&CssSelector>,
+    context: ExtractContext,
+    skip_unmatched: bool,
+) -> Result<TokenStream> {
+    let root = context.root();
+    let variants = match data {
+        Data::Struct(fields) => {
+            let fields = fields.iter().collect::<Vec<_>>();
+            let field_extractors =
+                generate_field_extractors(&type_name.to_string(), item_selector, &fields, &root)?;
+            return Ok(quote! {
+                Self {
+                    #(#field_extractors),*
+                }
+            });
+        }
+        Data::Enum(variants) if variants.is_empty() => {
+            return Err(Error::new(
+                type_name.span(),
+                "css selector enum must have at least one variant",
+            ));
+        }
+        Data::Enum(variants) => variants,
+    };
+
+    let mut branches = Vec::with_capacity(variants.len());
+    let mut fallback = None;
+    for v in variants {
+        if fallback.is_some() {
+            return Err(Error::new(
+                v.ident.span(),
+                "unreachable variant: the previous variant has no `when` selector",
+            ));
+        }
+        let variant_ident = &v.ident;
+        let fields = v.fields.iter().collect::<Vec<_>>();
+        let field_extractors = generate_field_extractors(
+            &format!("{}::{}", type_name, variant_ident),
+            item_selector,
+            &fields,
+            &root,
+        )?;
+        let constructor = if v.fields.is_unit() {
+            quote! { Self::#variant_ident }
+        } else {
+            quote! {
+                Self::#variant_ident {
+                    #(#field_extractors),*
+                }
+            }
+        };
+        match &v.when {
+            Some(when) => {
+                let matches = context.matches(when);
+                branches.push(quote! { if #matches { #constructor } });
+            }
+            None => fallback = Some(constructor),
+        }
+    }
+    let fallback = fallback.unwrap_or_else(|| {
+        let enum_name = type_name.to_string();
+        if skip_unmatched {
+            return quote! { continue };
+        }
+        quote! {
+            return Err(::reqwest_scraper::error::ScraperError::CssSelectorMatchError(::std::format!(
+                "no variant of `{}` matched the element",
+                #enum_name
+            )))
+        }
+    });
+    Ok(quote! {
+        #(#branches else)* { #fallback }
     })
 }
 
-fn generate_field_extractors(fields: Vec<&CssSelectorStructField>) -> Result<Vec<TokenStream>> {
+/// `root` is the element that fields without a selector path are extracted from
+fn generate_field_extractors(
+    struct_name: &str,
+    item_selector: Option<&CssSelector>,
+    fields: &[&CssSelectorStructField],
+    root: &TokenStream,
+) -> Result<Vec<TokenStream>> {
     let mut tokens = Vec::with_capacity(fields.len());
-    for f in fields.into_iter() {
+    for f in fields.iter() {
         let field_ident = f.ident.as_ref().ok_or_else(|| {
             Error::new(
                 f.ident.span(),
@@ -99,13 +279,58 @@ fn generate_field_extractors(fields: Vec<&CssSelectorStructField>) -> Result<Vec
         let extractor = Extractor::from_field(f)?;
         let default = &f.default;
         let ty = get_type_detail(&f.ty);
+        if f.nested.is_present() {
+            tokens.push(generate_nested_field_extractor(f, field_ident, ty)?);
+            continue;
+        }
         if default.is_none() && ty.is_other() {
             return Err(Error::new(
                 field_ident.span(),
                 "Non-option field need to be given a default value: selector(default=\"xxx\")",
             ));
         }
-        tokens.push(match &f.path {
+        if extractor.is_text() {
+            let field_name = field_ident.to_string();
+            let selector = match (&f.path, item_selector) {
+                (Some(path), _) => path.0.clone(),
+                (None, Some(item_selector)) => item_selector.0.clone(),
+                (None, None) => ":scope".into(),
+            };
+            let parse = quote! {
+                |text: ::std::string::String| ::reqwest_scraper::css_selector::parse_field(#struct_name, #field_name, #selector, text)
+            };
+            let extracted = match &f.path {
+                Some(selector) => {
+                    let selector = selector.compiled();
+                    quote! { item.select(#selector)?.first().and_then(#extractor) }
+                }
+                None => quote! { ::std::option::Option::Some(#root).and_then(#extractor) },
+            };
+            tokens.push(match ty {
+                PathType::Option => quote! {
+                    #field_ident: #extracted.map(#parse).transpose()?
+                },
+                PathType::Vector => match f.path.as_ref().map(CssSelector::compiled) {
+                    Some(selector) => quote! {
+                        #field_ident: item.select(#selector)?.iter()
+                                        .filter_map(#extractor)
+                                        .map(#parse)
+                                        .collect::<::reqwest_scraper::error::Result<::std::vec::Vec<_>>>()?
+                    },
+                    None => {
+                        return Err(Error::new(
+                            field_ident.span(),
+                            "Vec field must has selector path: selector(path=\"xxx\")",
+                        ));
+                    }
+                },
+                PathType::Other => quote! {
+                    #field_ident: (#parse)(#extracted.unwrap_or_else(|| #default.into()))?
+                },
+            });
+            continue;
+        }
+        tokens.push(match f.path.as_ref().map(CssSelector::compiled) {
             Some(selector) => {
                 match ty {
                     PathType::Option=>quote! {
@@ -126,7 +351,7 @@ fn generate_field_extractors(fields: Vec<&CssSelectorStructField>) -> Result<Vec
             None => {
                 match ty {
                     PathType::Option=>quote! {
-                        #field_ident: ::std::option::Option::Some(item).and_then(#extractor).into()
+                        #field_ident: ::std::option::Option::Some(#root).and_then(#extractor).into()
                     },
                     PathType::Vector=>{
                         return Err(Error::new(
@@ -135,7 +360,7 @@ fn generate_field_extractors(fields: Vec<&CssSelectorStructField>) -> Result<Vec
                         ));
                     },
                     PathType::Other=>quote! {
-                        #field_ident: ::std::option::Option::Some(item).and_then(#extractor).unwrap_or(#default.into()).into()
+                        #field_ident: ::std::option::Option::Some(#root).and_then(#extractor).unwrap_or(#default.into()).into()
                     }
                 }
             },
@@ -144,6 +369,56 @@ fn generate_field_extractors(fields: Vec<&CssSelectorStructField>) -> Result<Vec
     Ok(tokens)
 }
 
+/// Nested fields are extracted by the `FromCssSelector` implementation of the field type,
+/// relative to the elements matched by the field's selector path
+fn generate_nested_field_extractor(
+    f: &CssSelectorStructField,
+    field_ident: &syn::Ident,
+    ty: PathType,
+) -> Result<TokenStream> {
+    let selector = f.path.as_ref().ok_or_else(|| {
+        Error::new(
+            field_ident.span(),
+            "nested field must has selector path: selector(path=\"xxx\", nested)",
+        )
+    })?;
+    if f.default.is_some() {
+        return Err(Error::new(
+            field_ident.span(),
+            "nested field can't be given a default value",
+        ));
+    }
+    let field_ty = &f.ty;
+    let nested_ty = match ty {
+        PathType::Other => field_ty,
+        PathType::Option | PathType::Vector => get_generic_type(field_ty)
+            .ok_or_else(|| Error::new(field_ty.span(), "missing generic type of nested field"))?,
+    };
+    let compiled = selector.compiled();
+    Ok(match ty {
+        PathType::Option => quote! {
+            #field_ident: item.select(#compiled)?.first()
+                            .map(|e| <#nested_ty as ::reqwest_scraper::FromCssSelector>::from_select_item(&e))
+                            .transpose()?
+        },
+        PathType::Vector => quote! {
+            #field_ident: item.select(#compiled)?.iter()
+                            .map(|e| <#nested_ty as ::reqwest_scraper::FromCssSelector>::from_select_item(&e))
+                            .collect::<::reqwest_scraper::error::Result<::std::vec::Vec<_>>>()?
+        },
+        PathType::Other => quote! {
+            #field_ident: <#nested_ty as ::reqwest_scraper::FromCssSelector>::from_select_item(
+                &item.select(#compiled)?.first().ok_or_else(|| {
+                    ::reqwest_scraper::error::ScraperError::CssSelectorMatchError(::std::format!(
+                        "The \"{}\" css selector did not match any element",
+                        #selector
+                    ))
+                })?,
+            )?
+        },
+    })
+}
+
 #[derive(Debug)]
 pub enum Extractor {
     Name,
@@ -157,6 +432,11 @@ pub enum Extractor {
 }
 
 impl Extractor {
+    /// Text extractors produce a `String` that is parsed into the field type via `FromStr`
+    fn is_text(&self) -> bool {
+        !matches!(self, Self::HasClass(_) | Self::Map(_))
+    }
+
     fn from_field(field: &CssSelectorStructField) -> Result<Self> {
         let mut exists = 0;
         let mut result = Self::Html;
@@ -201,12 +481,16 @@ impl Extractor {
             result = Self::Map(map.clone());
             span = field.map.span();
         }
+        if field.nested.is_present() {
+            exists += 1;
+            span = field.nested.span();
+        }
         if exists <= 1 {
             Ok(result)
         } else {
             Err(Error::new(
                 span,
-                "[id,name,text,html,inner_html,has_class=\"class_name\",attr=\"attr_name\",map=\"map_func\"] must select only one",
+                "[id,name,text,html,inner_html,has_class=\"class_name\",attr=\"attr_name\",map=\"map_func\",nested] must select only one",
             ))
         }
     }
@@ -231,16 +515,35 @@ impl FromStr for CssSelector {
     type Err = syn::Error;
 
     fn from_str(selector: &str) -> Result<Self> {
-        let _ = Selector::parse(selector).map(|_| ()).map_err(|err| {
+        Selector::parse(selector).map_err(|err| {
             syn::Error::new(
                 Span::call_site(),
                 format!("invalid css selector `{}`: {:?}", selector, err),
             )
-        });
+        })?;
         Ok(CssSelector(selector.to_string()))
     }
 }
 
+impl CssSelector {
+    /// Expression of the selector compiled once per process, the selector was validated by `from_str`,
+    /// a selector that fails to compile anyway is reported as `ScraperError::CssSelectorError`
+    fn compiled(&self) -> TokenStream {
+        let selector = &self.0;
+        quote! {{
+            static SELECTOR: ::std::sync::OnceLock<
+                ::std::result::Result<::reqwest_scraper::css_selector::Selector, ::std::string::String>,
+            > = ::std::sync::OnceLock::new();
+            SELECTOR
+                .get_or_init(|| {
+                    ::reqwest_scraper::css_selector::Selector::parse(#selector).map_err(|e| e.to_string())
+                })
+                .as_ref()
+                .map_err(|e| ::reqwest_scraper::error::ScraperError::CssSelectorError(e.clone()))?
+        }}
+    }
+}
+
 impl FromMeta for CssSelector {
     fn from_string(s: &str) -> darling::Result<Self> {
         s.parse().map_err(darling::Error::from)
@@ -305,3 +608,26 @@ pub struct ExtractListResult {
 
     Ok(())
 }
+
+#[test]
+fn test_skip_unmatched_misuse() {
+    let on_struct = r#"
+#[derive(FromCssSelector)]
+#[selector(path = ".item", skip_unmatched)]
+pub struct Item {
+    #[selector(path = "h2", text)]
+    title: String,
+}"#;
+    let without_path = r#"
+#[derive(FromCssSelector)]
+#[selector(skip_unmatched)]
+pub enum Item {
+    #[selector(when = ".ad")]
+    Ad,
+}"#;
+
+    for input in [on_struct, without_path] {
+        let parsed = syn::parse_str(input).unwrap();
+        assert!(expand_derive_from_response(parsed).is_err());
+    }
+}
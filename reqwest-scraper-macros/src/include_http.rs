@@ -25,7 +25,7 @@ pub fn expand_macro(input: IncludeHttp) -> syn::Result<TokenStream> {
         for pair in punctuated {
             let path = &pair.path;
             let value = &pair.value;
-            let key = format!("{{{{{}}}}}", quote::quote!(#path).to_string());
+            let key = format!("{{{{{}}}}}", quote::quote!(#path));
             let value = match value {
                 syn::Expr::Lit(expr_lit) => {
                     if let syn::Lit::Str(ref lit_str) = expr_lit.lit {
@@ -58,7 +58,7 @@ fn parse_and_substitute(input: &str) -> String {
     let mut vars = HashMap::new();
 
     // 1. 提取所有变量定义
-    for cap in var_def_re.captures_iter(&input) {
+    for cap in var_def_re.captures_iter(input) {
         let key = cap[1].to_string();
         let value = cap[2].trim().to_string();
         vars.insert(key, value);
@@ -95,7 +95,7 @@ fn parse_and_substitute(input: &str) -> String {
         .collect();
 
     // 3. 替换正文中的 {{变量}}
-    let input_without_defs = var_def_re.replace_all(&input, "").to_string();
+    let input_without_defs = var_def_re.replace_all(input, "").to_string();
     let http_content = var_use_re.replace_all(&input_without_defs, |caps: &regex::Captures| {
         let key = &caps[1];
         resolved_vars
@@ -246,7 +246,7 @@ struct HttpRequest<'f> {
 }
 
 impl<'f> HttpRequest<'f> {
-    fn collect_args(&self) -> Vec<FormatArg> {
+    fn collect_args(&self) -> Vec<FormatArg<'_>> {
         let Self {
             url, headers, body, ..
         } = self;
@@ -254,7 +254,7 @@ impl<'f> HttpRequest<'f> {
         if let StrEnum::Format(fmt) = url {
             args = Self::push_arg_while_unique_name(args, fmt);
         }
-        for (_, value) in headers {
+        for value in headers.values() {
             if let StrEnum::Format(fmt) = value {
                 args = Self::push_arg_while_unique_name(args, fmt);
             }
@@ -273,12 +273,12 @@ impl<'f> HttpRequest<'f> {
             if args.iter().any(|a| a.name == fmt_args.name) {
                 continue;
             }
-            args.push(fmt_args.clone());
+            args.push(*fmt_args);
         }
         args
     }
 
-    fn collect_envs(&self) -> Vec<EnvVariable> {
+    fn collect_envs(&self) -> Vec<EnvVariable<'_>> {
         let Self {
             url, headers, body, ..
         } = self;
@@ -286,7 +286,7 @@ impl<'f> HttpRequest<'f> {
         if let StrEnum::Format(fmt) = url {
             envs = Self::push_env_while_unique_name(envs, fmt);
         }
-        for (_, value) in headers {
+        for value in headers.values() {
             if let StrEnum::Format(fmt) = value {
                 envs = Self::push_env_while_unique_name(envs, fmt);
             }
@@ -305,7 +305,7 @@ impl<'f> HttpRequest<'f> {
             if envs.iter().any(|a| a.name == fmt_env.name) {
                 continue;
             }
-            envs.push(fmt_env.clone());
+            envs.push(*fmt_env);
         }
         envs
     }
@@ -452,7 +452,7 @@ impl<'f> ToTokens for FormatArg<'f> {
         let Self { name, ty } = self;
         let name = Ident::new(name, Span::call_site());
         let ty = ty.unwrap_or("&str");
-        let ty: Type = syn::parse_str(ty).expect(&format!("type is invalid: {ty}"));
+        let ty: Type = syn::parse_str(ty).unwrap_or_else(|_| panic!("type is invalid: {ty}"));
         tokens.extend(quote! {#name: #ty});
     }
 }
@@ -501,9 +501,9 @@ mod tests {
 ### request_baidu
 GET https://www.baidu.com
 "####;
-        let http = parse_http(&req, &None);
+        let http = parse_http(req, &None);
         assert_eq!(http.len(), 1);
-        let req = http.get(0).unwrap();
+        let req = http.first().unwrap();
         assert_eq!(req.name, "request_baidu");
         assert_eq!(req.request.method, "GET");
 
@@ -522,9 +522,9 @@ GET https://www.baidu.com/s?kw=xxx
 User-Agent: reqwest
 token: xxxx1234ABCD
 "####;
-        let http = parse_http(&req, &None);
+        let http = parse_http(req, &None);
         assert_eq!(http.len(), 1);
-        let req = http.get(0).unwrap();
+        let req = http.first().unwrap();
         assert_eq!(req.name, "request_baidu");
         let request = &req.request;
         assert_eq!(request.method, "GET");
@@ -556,9 +556,9 @@ Content-Type:   application/json
 
 {"body":"msg"}
 "####;
-        let http = parse_http(&req, &None);
+        let http = parse_http(req, &None);
         assert_eq!(http.len(), 1);
-        let req = http.get(0).unwrap();
+        let req = http.first().unwrap();
         assert_eq!(req.name, "request_baidu");
         let request = &req.request;
         assert_eq!(request.method, "GET");
@@ -0,0 +1,237 @@
+use crate::utils::syn::{get_generic_type, get_type_detail, PathType};
+use darling::{ast::Data, util::Flag, FromDeriveInput, FromField};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{spanned::Spanned, DeriveInput, Error, Result};
+
+#[derive(Debug, FromDeriveInput)]
+#[darling(attributes(jsonpath), supports(struct_named))]
+struct JsonPathScraper {
+    ident: syn::Ident,
+    generics: syn::Generics,
+    data: Data<(), JsonPathStructField>,
+    path: Option<String>,
+}
+
+#[derive(Debug, FromField)]
+#[darling(attributes(jsonpath))]
+struct JsonPathStructField {
+    ident: Option<syn::Ident>,
+    ty: syn::Type,
+    path: String,
+    default: Option<String>,
+    nested: Flag,
+}
+
+pub fn expand_derive_from_response(input: DeriveInput) -> syn::Result<TokenStream> {
+    let scraper = JsonPathScraper::from_derive_input(&input)?;
+
+    let type_name = scraper.ident;
+    let generics = scraper.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let fields = scraper
+        .data
+        .take_struct()
+        .ok_or_else(|| Error::new(type_name.span(), "jsonpath derive only supports struct"))?;
+    let struct_name = type_name.to_string();
+    let field_extractors = fields
+        .iter()
+        .map(|f| generate_field_extractor(&struct_name, f))
+        .collect::<Result<Vec<_>>>()?;
+    let constructor = quote! {
+        Self {
+            #(#field_extractors),*
+        }
+    };
+
+    Ok(match scraper.path {
+        Some(path) => {
+            let path = compiled(&path);
+            quote! {
+                impl #impl_generics ::reqwest_scraper::FromJsonPath for #type_name #ty_generics #where_clause {
+                    type JsonPathExtractResult = ::reqwest_scraper::error::Result<std::vec::Vec<Self>>;
+                    fn from_value_with(
+                        value: &::reqwest_scraper::jsonpath::Value,
+                        backend: ::reqwest_scraper::jsonpath::JsonPathBackend,
+                    ) -> Self::JsonPathExtractResult {
+                        let list = ::reqwest_scraper::jsonpath::select_values_with(value, #path, backend)?;
+                        let mut result: Vec<Self> = std::vec::Vec::new();
+
+                        for item in list.into_iter() {
+                            let extract_item = #constructor;
+                            result.push(extract_item);
+                        }
+
+                        Ok(result)
+                    }
+                }
+            }
+        }
+        None => {
+            quote! {
+                impl #impl_generics ::reqwest_scraper::FromJsonPath for #type_name #ty_generics #where_clause {
+                    type JsonPathExtractResult = ::reqwest_scraper::error::Result<Self>;
+                    fn from_value_with(
+                        value: &::reqwest_scraper::jsonpath::Value,
+                        backend: ::reqwest_scraper::jsonpath::JsonPathBackend,
+                    ) -> Self::JsonPathExtractResult {
+                        let item = value;
+
+                        Ok(#constructor)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Expression evaluating to a `&'static JsonPathCache`, which compiles the path once per backend
+fn compiled(path: &str) -> TokenStream {
+    quote! {
+        {
+            static JSONPATH: ::reqwest_scraper::jsonpath::JsonPathCache =
+                ::reqwest_scraper::jsonpath::JsonPathCache::new(#path);
+            &JSONPATH
+        }
+    }
+}
+
+/// Every matched value is deserialized into the field type,
+/// nested fields are extracted by the `FromJsonPath` implementation of the field type
+fn generate_field_extractor(struct_name: &str, f: &JsonPathStructField) -> Result<TokenStream> {
+    let field_ident = f.ident.as_ref().ok_or_else(|| {
+        Error::new(
+            f.ident.span(),
+            "jsonpath struct should never be tuple struct",
+        )
+    })?;
+    let default = &f.default;
+    let ty = get_type_detail(&f.ty);
+    let path = &f.path;
+    let compiled_path = compiled(path);
+    let values = quote! { ::reqwest_scraper::jsonpath::select_values_with(item, #compiled_path, backend)? };
+    if f.nested.is_present() {
+        if default.is_some() {
+            return Err(Error::new(
+                field_ident.span(),
+                "nested field can't be given a default value",
+            ));
+        }
+        let field_ty = &f.ty;
+        let nested_ty = match ty {
+            PathType::Other => field_ty,
+            PathType::Option | PathType::Vector => get_generic_type(field_ty).ok_or_else(|| {
+                Error::new(field_ty.span(), "missing generic type of nested field")
+            })?,
+        };
+        return Ok(match ty {
+            PathType::Option => quote! {
+                #field_ident: #values
+                                .first()
+                                .map(|v| <#nested_ty as ::reqwest_scraper::FromJsonPath>::from_value_with(v, backend))
+                                .transpose()?
+            },
+            PathType::Vector => quote! {
+                #field_ident: #values
+                                .into_iter()
+                                .map(|v| <#nested_ty as ::reqwest_scraper::FromJsonPath>::from_value_with(v, backend))
+                                .collect::<::reqwest_scraper::error::Result<::std::vec::Vec<_>>>()?
+            },
+            PathType::Other => quote! {
+                #field_ident: <#nested_ty as ::reqwest_scraper::FromJsonPath>::from_value_with(
+                    #values.first().ok_or_else(|| {
+                        ::reqwest_scraper::error::ScraperError::JsonPathNotFound {
+                            path: ::std::string::ToString::to_string(#path),
+                        }
+                    })?,
+                    backend,
+                )?
+            },
+        });
+    }
+    if default.is_none() && ty.is_other() {
+        return Err(Error::new(
+            field_ident.span(),
+            "Non-option field need to be given a default value: jsonpath(default=\"xxx\")",
+        ));
+    }
+    let field_name = field_ident.to_string();
+    let parse = quote! {
+        |value: &::reqwest_scraper::jsonpath::Value| ::reqwest_scraper::jsonpath::parse_field(#struct_name, #field_name, #path, value)
+    };
+    Ok(match ty {
+        PathType::Option => quote! {
+            #field_ident: match #values.first() {
+                ::std::option::Option::Some(value) => (#parse)(value)?,
+                ::std::option::Option::None => ::std::option::Option::None,
+            }
+        },
+        PathType::Vector => quote! {
+            #field_ident: #values.into_iter()
+                            .map(#parse)
+                            .collect::<::reqwest_scraper::error::Result<::std::vec::Vec<_>>>()?
+        },
+        PathType::Other => quote! {
+            #field_ident: match #values.first() {
+                ::std::option::Option::Some(value) => (#parse)(value)?,
+                ::std::option::Option::None => ::reqwest_scraper::jsonpath::parse_default(#struct_name, #field_name, #path, #default)?,
+            }
+        },
+    })
+}
+
+#[test]
+fn test_select_list() -> Result<()> {
+    let input = r#"
+#[derive(FromJsonPath)]
+#[jsonpath(path = "$.data.items[*]")]
+pub struct Item {
+    #[jsonpath(path = "$.id", default = "0")]
+    id: i64,
+
+    #[jsonpath(path = "$.owner.login")]
+    owner: Option<String>,
+
+    #[jsonpath(path = "$.tags[*]")]
+    tags: Vec<String>,
+}"#;
+
+    let parsed = syn::parse_str(input).unwrap();
+    let receiver = JsonPathScraper::from_derive_input(&parsed)?;
+    let tokens = expand_derive_from_response(parsed)?;
+
+    println!(
+        "INPUT: \n{}\n\nPARSED AS: \n{:#?}\n\nEMITS: \n{}",
+        input, receiver, tokens
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_select_nested() -> Result<()> {
+    let input = r#"
+#[derive(FromJsonPath)]
+pub struct Response {
+    #[jsonpath(path = "$.meta.total", default = "0")]
+    total: u64,
+
+    #[jsonpath(path = "$.data.user", nested)]
+    user: Option<User>,
+
+    #[jsonpath(path = "$.data.items[*]", nested)]
+    items: Vec<Item>,
+}"#;
+
+    let parsed = syn::parse_str(input).unwrap();
+    let receiver = JsonPathScraper::from_derive_input(&parsed)?;
+    let tokens = expand_derive_from_response(parsed)?;
+
+    println!(
+        "INPUT: \n{}\n\nPARSED AS: \n{:#?}\n\nEMITS: \n{}",
+        input, receiver, tokens
+    );
+
+    Ok(())
+}
@@ -1,5 +1,6 @@
 mod css_selector;
 mod include_http;
+mod jsonpath;
 mod utils;
 mod xpath;
 
@@ -25,6 +26,15 @@ pub fn derive_xpath(input: TokenStream) -> TokenStream {
         .into()
 }
 
+#[proc_macro_derive(FromJsonPath, attributes(jsonpath))]
+pub fn derive_jsonpath(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    jsonpath::expand_derive_from_response(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
 #[proc_macro]
 pub fn include_http(input: TokenStream) -> TokenStream {
     let input: IncludeHttp = match syn::parse(input) {
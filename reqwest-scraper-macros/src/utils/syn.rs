@@ -1,4 +1,4 @@
-use syn::Type;
+use syn::{GenericArgument, PathArguments, Type};
 
 pub(crate) fn get_type_detail(ty: &Type) -> PathType {
     match ty {
@@ -35,6 +35,22 @@ pub(crate) fn get_type_detail(ty: &Type) -> PathType {
     }
 }
 
+/// Returns the first generic argument of a type, e.g. `T` of `Option<T>` or `Vec<T>`
+pub(crate) fn get_generic_type(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Path(typepath) if typepath.qself.is_none() => {
+            match &typepath.path.segments.last()?.arguments {
+                PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+                    GenericArgument::Type(ty) => Some(ty),
+                    _ => None,
+                }),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 pub(crate) enum PathType {
     Option,
     Vector,
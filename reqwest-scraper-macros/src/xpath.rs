@@ -1,16 +1,29 @@
-use crate::utils::syn::{get_type_detail, PathType};
-use darling::{ast::Data, FromDeriveInput, FromField};
+use crate::utils::syn::{get_generic_type, get_type_detail, PathType};
+use darling::{
+    ast::{Data, Fields},
+    util::Flag,
+    FromDeriveInput, FromField, FromVariant,
+};
 use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{spanned::Spanned, DeriveInput, Error, Result};
 
 #[derive(Debug, FromDeriveInput)]
-#[darling(attributes(xpath), supports(struct_named))]
+#[darling(attributes(xpath), supports(struct_named, enum_named, enum_unit))]
 struct XPathScraper {
     ident: syn::Ident,
     generics: syn::Generics,
-    data: Data<(), XPathStructField>,
+    data: Data<XPathVariant, XPathStructField>,
     path: Option<String>,
+    skip_unmatched: Flag,
+}
+
+#[derive(Debug, FromVariant)]
+#[darling(attributes(xpath))]
+struct XPathVariant {
+    ident: syn::Ident,
+    fields: Fields<XPathStructField>,
+    when: Option<String>,
 }
 
 #[derive(Debug, FromField)]
@@ -20,36 +33,50 @@ struct XPathStructField {
     ty: syn::Type,
     path: String,
     default: Option<String>,
+    nested: Flag,
 }
 
 pub fn expand_derive_from_response(input: DeriveInput) -> syn::Result<TokenStream> {
     let scraper = XPathScraper::from_derive_input(&input)?;
+    let skip_unmatched = check_skip_unmatched(&scraper)?;
 
     let type_name = scraper.ident;
     let generics = scraper.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    let fields = scraper
-        .data
-        .as_ref()
-        .take_struct()
-        .ok_or_else(|| Error::new(input.span(), "xpath should never be used on enum"))?
-        .fields;
+    let node_constructor = generate_constructor(
+        &type_name,
+        &scraper.data,
+        ExtractContext::Node,
+        skip_unmatched,
+    )?;
 
     Ok(match scraper.path {
         Some(xpath) => {
-            let field_extractors = generate_list_item_field_extractors(fields)?;
+            let xpath = compiled(&xpath);
             quote! {
                 impl #impl_generics ::reqwest_scraper::FromXPath for #type_name #ty_generics #where_clause {
                     type XPathExtractResult = ::reqwest_scraper::error::Result<std::vec::Vec<Self>>;
                     fn from_xhtml(html: ::reqwest_scraper::xpath::XHtml) -> Self::XPathExtractResult {
-                        let list = html.select(#xpath)?.as_nodes();
+                        let mut context = html.context()?;
+                        let list = context.select(#xpath)?.as_nodes();
                         let mut result: Vec<Self> = std::vec::Vec::new();
 
                         for item in list.iter() {
-                            let extract_item = Self {
-                                #(#field_extractors),*
-                            };
+                            let extract_item = #node_constructor;
+                            result.push(extract_item);
+                        }
+
+                        Ok(result)
+                    }
+
+                    fn from_node(node: &::reqwest_scraper::xpath::Node) -> Self::XPathExtractResult {
+                        let mut context = node.context()?;
+                        let list = context.findnodes(node, #xpath)?;
+                        let mut result: Vec<Self> = std::vec::Vec::new();
+
+                        for item in list.iter() {
+                            let extract_item = #node_constructor;
                             result.push(extract_item);
                         }
 
@@ -59,16 +86,22 @@ pub fn expand_derive_from_response(input: DeriveInput) -> syn::Result<TokenStrea
             }
         }
         None => {
-            let field_extractors = generate_field_extractors(fields)?;
+            let document_constructor =
+                generate_constructor(&type_name, &scraper.data, ExtractContext::Document, false)?;
             quote! {
                 impl #impl_generics ::reqwest_scraper::FromXPath for #type_name #ty_generics #where_clause {
                     type XPathExtractResult = ::reqwest_scraper::error::Result<Self>;
                     fn from_xhtml(html: ::reqwest_scraper::xpath::XHtml) -> Self::XPathExtractResult {
-                        let item = &html;
+                        let mut context = html.context()?;
 
-                        Ok(Self {
-                            #(#field_extractors),*
-                        })
+                        Ok(#document_constructor)
+                    }
+
+                    fn from_node(node: &::reqwest_scraper::xpath::Node) -> Self::XPathExtractResult {
+                        let item = node;
+                        let mut context = node.context()?;
+
+                        Ok(#node_constructor)
                     }
                 }
             }
@@ -76,64 +109,269 @@ pub fn expand_derive_from_response(input: DeriveInput) -> syn::Result<TokenStrea
     })
 }
 
-fn generate_list_item_field_extractors(fields: Vec<&XPathStructField>) -> Result<Vec<TokenStream>> {
-    let mut tokens = Vec::with_capacity(fields.len());
-    for f in fields.into_iter() {
-        let field_ident = f.ident.as_ref().ok_or_else(|| {
-            Error::new(f.ident.span(), "xpath struct should never be tuple struct")
-        })?;
-        let default = &f.default;
-        let ty = get_type_detail(&f.ty);
-        if default.is_none() && ty.is_other() {
+/// `skip_unmatched` only applies to the items of a list of enums
+fn check_skip_unmatched(scraper: &XPathScraper) -> Result<bool> {
+    let flag = &scraper.skip_unmatched;
+    if flag.is_present() && !scraper.data.is_enum() {
+        return Err(Error::new(
+            flag.span(),
+            "`skip_unmatched` is only supported on enums",
+        ));
+    }
+    if flag.is_present() && scraper.path.is_none() {
+        return Err(Error::new(
+            flag.span(),
+            "`skip_unmatched` requires the `path` xpath of the list items",
+        ));
+    }
+    Ok(flag.is_present())
+}
+
+/// Expression evaluating to an `XPath` compiled once per thread on first use,
+/// a compiled xpath isn't `Sync` since libxml2 writes to it while evaluating
+fn compiled(xpath: &str) -> TokenStream {
+    quote! {
+        {
+            ::std::thread_local! {
+                static XPATH: ::std::option::Option<::reqwest_scraper::xpath::XPath> =
+                    ::reqwest_scraper::xpath::XPath::compile(#xpath).ok();
+            }
+            XPATH.with(|xpath| xpath.clone()).ok_or_else(|| {
+                ::reqwest_scraper::error::ScraperError::XPathError(::std::format!("xpath parse failed:{}", #xpath))
+            })?
+        }
+    }
+}
+
+/// What the generated code evaluates against, through the `context: XPathContext`
+#[derive(Clone, Copy)]
+enum ExtractContext {
+    /// The whole `XHtml` document
+    Document,
+    /// The `item: &Node`
+    Node,
+}
+
+/// Generate the expression that constructs `Self` in the given context.
+///
+/// For enums, the first variant whose `when` xpath evaluates to true is constructed,
+/// a variant without `when` always matches.
+/// With `skip_unmatched`, a node of the list that no variant matches is skipped, rather than an error.
+fn generate_constructor(
+    type_name: &syn::Ident,
+    data: &Data<XPathVariant, XPathStructField>,
+    context: ExtractContext,
+    skip_unmatched: bool,
+) -> Result<TokenStream> {
+    let generate = |struct_name: &str, fields: &[&XPathStructField]| match context {
+        ExtractContext::Document => generate_field_extractors(struct_name, fields),
+        ExtractContext::Node => generate_list_item_field_extractors(struct_name, fields),
+    };
+    let variants = match data {
+        Data::Struct(fields) => {
+            let fields = fields.iter().collect::<Vec<_>>();
+            let field_extractors = generate(&type_name.to_string(), &fields)?;
+            return Ok(quote! {
+                Self {
+                    #(#field_extractors),*
+                }
+            });
+        }
+        Data::Enum(variants) if variants.is_empty() => {
             return Err(Error::new(
-                field_ident.span(),
-                "Non-option field need to be given a default value: xpath(default=\"xxx\")",
+                type_name.span(),
+                "xpath enum must have at least one variant",
             ));
         }
-        let xpath = &f.path;
-        tokens.push(match ty {
-            PathType::Option => quote! {
-                #field_ident: item.findvalue(#xpath)?
-            },
-            PathType::Vector => quote! {
-                #field_ident: item.findvalues(#xpath)?
-            },
-            PathType::Other => quote! {
-                #field_ident: item.findvalue(#xpath)?.unwrap_or(#default.into())
+        Data::Enum(variants) => variants,
+    };
+
+    let mut branches = Vec::with_capacity(variants.len());
+    let mut fallback = None;
+    for v in variants {
+        if fallback.is_some() {
+            return Err(Error::new(
+                v.ident.span(),
+                "unreachable variant: the previous variant has no `when` xpath",
+            ));
+        }
+        let variant_ident = &v.ident;
+        let fields = v.fields.iter().collect::<Vec<_>>();
+        let field_extractors = generate(&format!("{}::{}", type_name, variant_ident), &fields)?;
+        let constructor = if v.fields.is_unit() {
+            quote! { Self::#variant_ident }
+        } else {
+            quote! {
+                Self::#variant_ident {
+                    #(#field_extractors),*
+                }
+            }
+        };
+        match &v.when {
+            Some(when) => {
+                let when = compiled(when);
+                let matches = match context {
+                    ExtractContext::Document => quote! { context.matches(#when)? },
+                    ExtractContext::Node => quote! { context.matches_from(item, #when)? },
+                };
+                branches.push(quote! { if #matches { #constructor } })
+            }
+            None => fallback = Some(constructor),
+        }
+    }
+    let fallback = fallback.unwrap_or_else(|| {
+        let enum_name = type_name.to_string();
+        if skip_unmatched {
+            return quote! { continue };
+        }
+        quote! {
+            return Err(::reqwest_scraper::error::ScraperError::XPathError(::std::format!(
+                "no variant of `{}` matched the node",
+                #enum_name
+            )))
+        }
+    });
+    Ok(quote! {
+        #(#branches else)* { #fallback }
+    })
+}
+
+/// Fields evaluated relative to a `Node`
+fn generate_list_item_field_extractors(
+    struct_name: &str,
+    fields: &[&XPathStructField],
+) -> Result<Vec<TokenStream>> {
+    let mut tokens = Vec::with_capacity(fields.len());
+    for f in fields.iter() {
+        let xpath = compiled(&f.path);
+        tokens.push(generate_field_extractor(
+            struct_name,
+            f,
+            Evaluation {
+                value: quote! { context.findvalue(item, #xpath)? },
+                values: quote! { context.findvalues(item, #xpath)? },
+                node: quote! { context.findnode(item, #xpath)? },
+                nodes: quote! { context.findnodes(item, #xpath)? },
             },
-        })
+        )?)
     }
     Ok(tokens)
 }
 
-fn generate_field_extractors(fields: Vec<&XPathStructField>) -> Result<Vec<TokenStream>> {
+/// Fields evaluated against the whole `XHtml` document
+fn generate_field_extractors(
+    struct_name: &str,
+    fields: &[&XPathStructField],
+) -> Result<Vec<TokenStream>> {
     let mut tokens = Vec::with_capacity(fields.len());
-    for f in fields.into_iter() {
-        let field_ident = f.ident.as_ref().ok_or_else(|| {
-            Error::new(f.ident.span(), "xpath struct should never be tuple struct")
-        })?;
-        let default = &f.default;
-        let ty = get_type_detail(&f.ty);
-        if default.is_none() && ty.is_other() {
+    for f in fields.iter() {
+        let xpath = compiled(&f.path);
+        tokens.push(generate_field_extractor(
+            struct_name,
+            f,
+            Evaluation {
+                value: quote! { context.select(#xpath)?.as_value() },
+                values: quote! { context.select(#xpath)?.as_values() },
+                node: quote! { context.select(#xpath)?.as_node() },
+                nodes: quote! { context.select(#xpath)?.as_nodes() },
+            },
+        )?);
+    }
+    Ok(tokens)
+}
+
+/// Expressions evaluating a field's xpath in the current context
+struct Evaluation {
+    /// `Option<String>`
+    value: TokenStream,
+    /// `Vec<String>`
+    values: TokenStream,
+    /// `Option<Node>`
+    node: TokenStream,
+    /// `Vec<Node>`
+    nodes: TokenStream,
+}
+
+/// Every extracted value is parsed into the field type through `FromStr`,
+/// nested fields are extracted by the `FromXPath` implementation of the field type
+fn generate_field_extractor(
+    struct_name: &str,
+    f: &XPathStructField,
+    eval: Evaluation,
+) -> Result<TokenStream> {
+    let field_ident = f
+        .ident
+        .as_ref()
+        .ok_or_else(|| Error::new(f.ident.span(), "xpath struct should never be tuple struct"))?;
+    let default = &f.default;
+    let ty = get_type_detail(&f.ty);
+    let xpath = &f.path;
+    let Evaluation {
+        value,
+        values,
+        node,
+        nodes,
+    } = eval;
+    if f.nested.is_present() {
+        if default.is_some() {
             return Err(Error::new(
                 field_ident.span(),
-                "Non-option field need to be given a default value: xpath(default=\"xxx\")",
+                "nested field can't be given a default value",
             ));
         }
-        let xpath = &f.path;
-        tokens.push(match ty {
+        let field_ty = &f.ty;
+        let nested_ty = match ty {
+            PathType::Other => field_ty,
+            PathType::Option | PathType::Vector => get_generic_type(field_ty).ok_or_else(|| {
+                Error::new(field_ty.span(), "missing generic type of nested field")
+            })?,
+        };
+        return Ok(match ty {
             PathType::Option => quote! {
-                #field_ident: item.select(#xpath)?.as_str().map(|s|s.into())
+                #field_ident: #node
+                                .map(|n| <#nested_ty as ::reqwest_scraper::FromXPath>::from_node(&n))
+                                .transpose()?
             },
             PathType::Vector => quote! {
-                #field_ident: item.select(#xpath)?.as_strs().map(|s|s.into())
+                #field_ident: #nodes.iter()
+                                .map(|n| <#nested_ty as ::reqwest_scraper::FromXPath>::from_node(n))
+                                .collect::<::reqwest_scraper::error::Result<::std::vec::Vec<_>>>()?
             },
             PathType::Other => quote! {
-                #field_ident: item.select(#xpath)?.as_str().map(|s|s.into()).unwrap_or(#default)
+                #field_ident: <#nested_ty as ::reqwest_scraper::FromXPath>::from_node(
+                    &#node.ok_or_else(|| {
+                        ::reqwest_scraper::error::ScraperError::XPathError(::std::format!(
+                            "The \"{}\" xpath did not match any node",
+                            #xpath
+                        ))
+                    })?,
+                )?
             },
         });
     }
-    Ok(tokens)
+    if default.is_none() && ty.is_other() {
+        return Err(Error::new(
+            field_ident.span(),
+            "Non-option field need to be given a default value: xpath(default=\"xxx\")",
+        ));
+    }
+    let field_name = field_ident.to_string();
+    let parse = quote! {
+        |text: ::std::string::String| ::reqwest_scraper::xpath::parse_field(#struct_name, #field_name, #xpath, text)
+    };
+    Ok(match ty {
+        PathType::Option => quote! {
+            #field_ident: #value.map(#parse).transpose()?
+        },
+        PathType::Vector => quote! {
+            #field_ident: #values.into_iter()
+                            .map(#parse)
+                            .collect::<::reqwest_scraper::error::Result<::std::vec::Vec<_>>>()?
+        },
+        PathType::Other => quote! {
+            #field_ident: (#parse)(#value.unwrap_or_else(|| #default.into()))?
+        },
+    })
 }
 
 #[test]
@@ -187,3 +425,26 @@ pub struct ExtractListResult {
 
     Ok(())
 }
+
+#[test]
+fn test_skip_unmatched_misuse() {
+    let on_struct = r#"
+#[derive(FromXPath)]
+#[xpath(path = "//div", skip_unmatched)]
+pub struct Item {
+    #[xpath(path = "./h2/text()")]
+    title: String,
+}"#;
+    let without_path = r#"
+#[derive(FromXPath)]
+#[xpath(skip_unmatched)]
+pub enum Item {
+    #[xpath(when = "@data-type='ad'")]
+    Ad,
+}"#;
+
+    for input in [on_struct, without_path] {
+        let parsed = syn::parse_str(input).unwrap();
+        assert!(expand_derive_from_response(parsed).is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//!  Detect the character encoding of an HTML document from its bytes
+//!
+//! Follows the [WHATWG encoding sniffing algorithm](https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding):
+//! byte order mark, `Content-Type` charset, `<meta>` prescan of the first 1024 bytes
+//! and the XML declaration, then the statistical detection of the `charset_detection` feature.
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+use mime::Mime;
+
+/// Number of leading bytes searched for an encoding declaration
+pub const PRESCAN_BYTES: usize = 1024;
+
+/// How the encoding of a document was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodingSource {
+    /// Byte order mark at the start of the document
+    Bom,
+    /// `charset` parameter of the `Content-Type` header
+    ContentType,
+    /// `<meta charset>` or `<meta http-equiv="Content-Type">` in the document
+    Meta,
+    /// `encoding` of the `<?xml ?>` declaration
+    XmlDeclaration,
+    /// Statistical detection of the document bytes
+    Detected,
+    /// Nothing declared the encoding, UTF-8 is used
+    Default,
+}
+
+/// Encoding of a document and how it was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedEncoding {
+    /// encoding of the document
+    pub encoding: &'static Encoding,
+    /// how the encoding was determined
+    pub source: EncodingSource,
+}
+
+impl DetectedEncoding {
+    fn new(encoding: &'static Encoding, source: EncodingSource) -> Self {
+        Self { encoding, source }
+    }
+
+    /// Decode the document, the byte order mark is removed
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let (text, _, _) = self.encoding.decode(bytes);
+        text.into_owned()
+    }
+}
+
+/// Detect the encoding of an HTML document.
+///
+/// `content_type` is the value of the `Content-Type` header, if any.
+pub fn detect_encoding(bytes: &[u8], content_type: Option<&str>) -> DetectedEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return DetectedEncoding::new(encoding, EncodingSource::Bom);
+    }
+    if let Some(encoding) = content_type.and_then(content_type_encoding) {
+        return DetectedEncoding::new(encoding, EncodingSource::ContentType);
+    }
+    let head = &bytes[..bytes.len().min(PRESCAN_BYTES)];
+    if let Some(encoding) = xml_declaration_encoding(head) {
+        return DetectedEncoding::new(encoding, EncodingSource::XmlDeclaration);
+    }
+    if let Some(encoding) = prescan(head) {
+        return DetectedEncoding::new(encoding, EncodingSource::Meta);
+    }
+    detect_statistically(bytes)
+}
+
+/// Encoding of the `charset` parameter of a `Content-Type` header value
+pub fn content_type_encoding(content_type: &str) -> Option<&'static Encoding> {
+    let mime = content_type.parse::<Mime>().ok()?;
+    let charset = mime.get_param(mime::CHARSET)?;
+    Encoding::for_label(charset.as_str().as_bytes())
+}
+
+#[cfg(feature = "charset_detection")]
+fn detect_statistically(bytes: &[u8]) -> DetectedEncoding {
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    DetectedEncoding::new(detector.guess(None, true), EncodingSource::Detected)
+}
+
+#[cfg(not(feature = "charset_detection"))]
+fn detect_statistically(_bytes: &[u8]) -> DetectedEncoding {
+    DetectedEncoding::new(UTF_8, EncodingSource::Default)
+}
+
+/// Encodings declared in the document that can't be right for an ASCII compatible prescan
+fn declared_encoding(label: &[u8]) -> Option<&'static Encoding> {
+    let encoding = Encoding::for_label(label)?;
+    Some(if encoding == UTF_16BE || encoding == UTF_16LE {
+        UTF_8
+    } else if encoding == X_USER_DEFINED {
+        WINDOWS_1252
+    } else {
+        encoding
+    })
+}
+
+/// Encoding of the `<?xml version="1.0" encoding="..."?>` declaration
+fn xml_declaration_encoding(head: &[u8]) -> Option<&'static Encoding> {
+    let decl = head.strip_prefix(b"<?xml")?;
+    let decl = &decl[..find(decl, b"?>")?];
+    let mut pos = find(decl, b"encoding")? + b"encoding".len();
+    pos = skip_whitespace(decl, pos);
+    if decl.get(pos) != Some(&b'=') {
+        return None;
+    }
+    pos = skip_whitespace(decl, pos + 1);
+    let quote = *decl.get(pos).filter(|q| matches!(q, b'"' | b'\''))?;
+    let value = &decl[pos + 1..];
+    let end = value.iter().position(|b| *b == quote)?;
+    declared_encoding(&value[..end])
+}
+
+/// Prescan the bytes for a `<meta>` encoding declaration
+fn prescan(head: &[u8]) -> Option<&'static Encoding> {
+    let mut pos = 0;
+    while pos < head.len() {
+        let rest = &head[pos..];
+        if rest.starts_with(b"<!--") {
+            pos += find(&rest[2..], b"-->")? + 5;
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && rest
+                .get(5)
+                .is_some_and(|b| b.is_ascii_whitespace() || *b == b'/')
+        {
+            pos += 6;
+            let mut attrs = Vec::new();
+            let mut got_pragma = false;
+            let mut need_pragma = None;
+            let mut charset = None;
+            while let Some((name, value, next)) = attribute(head, pos) {
+                pos = next;
+                if attrs.contains(&name) {
+                    continue;
+                }
+                match name.as_slice() {
+                    b"http-equiv" => got_pragma |= value == b"content-type",
+                    b"content" if charset.is_none() => {
+                        if let Some(encoding) = meta_content_charset(&value) {
+                            charset = declared_encoding(encoding);
+                            need_pragma = Some(true);
+                        }
+                    }
+                    b"charset" => {
+                        charset = declared_encoding(&value);
+                        need_pragma = Some(false);
+                    }
+                    _ => {}
+                }
+                attrs.push(name);
+            }
+            match need_pragma {
+                Some(true) if !got_pragma => {}
+                Some(_) if charset.is_some() => return charset,
+                _ => {}
+            }
+        } else if rest.len() > 1
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic()
+                || (rest[1] == b'/' && rest.get(2).is_some_and(|b| b.is_ascii_alphabetic())))
+        {
+            pos += rest
+                .iter()
+                .position(|b| b.is_ascii_whitespace() || *b == b'>')
+                .unwrap_or(rest.len());
+            while let Some((_, _, next)) = attribute(head, pos) {
+                pos = next;
+            }
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos += rest.iter().position(|b| *b == b'>')? + 1;
+        } else {
+            pos += 1;
+        }
+    }
+    None
+}
+
+/// Read the attribute at `pos`, returns the lowercase name and value and the next position
+fn attribute(bytes: &[u8], mut pos: usize) -> Option<(Vec<u8>, Vec<u8>, usize)> {
+    while bytes
+        .get(pos)
+        .is_some_and(|b| b.is_ascii_whitespace() || *b == b'/')
+    {
+        pos += 1;
+    }
+    if *bytes.get(pos)? == b'>' {
+        return None;
+    }
+    let mut name = Vec::new();
+    loop {
+        match *bytes.get(pos)? {
+            b'=' if !name.is_empty() => break,
+            b if b.is_ascii_whitespace() => {
+                pos = skip_whitespace(bytes, pos);
+                if bytes.get(pos) != Some(&b'=') {
+                    return Some((name, Vec::new(), pos));
+                }
+                break;
+            }
+            b'/' | b'>' => return Some((name, Vec::new(), pos)),
+            b => name.push(b.to_ascii_lowercase()),
+        }
+        pos += 1;
+    }
+    pos = skip_whitespace(bytes, pos + 1);
+    let mut value = Vec::new();
+    match *bytes.get(pos)? {
+        quote @ (b'"' | b'\'') => {
+            let len = bytes[pos + 1..].iter().position(|b| *b == quote)?;
+            value.extend(bytes[pos + 1..pos + 1 + len].to_ascii_lowercase());
+            pos += len + 2;
+        }
+        b'>' => {}
+        _ => {
+            while let Some(b) = bytes
+                .get(pos)
+                .filter(|b| !b.is_ascii_whitespace() && **b != b'>')
+            {
+                value.push(b.to_ascii_lowercase());
+                pos += 1;
+            }
+        }
+    }
+    Some((name, value, pos))
+}
+
+/// Extract the charset of a `<meta http-equiv="Content-Type" content="...">` value
+fn meta_content_charset(content: &[u8]) -> Option<&[u8]> {
+    let mut pos = 0;
+    loop {
+        pos += find(&content[pos..], b"charset")? + b"charset".len();
+        let value = skip_whitespace(content, pos);
+        if content.get(value) == Some(&b'=') {
+            pos = skip_whitespace(content, value + 1);
+            break;
+        }
+    }
+    let rest = &content[pos..];
+    match *rest.first()? {
+        quote @ (b'"' | b'\'') => {
+            let end = rest[1..].iter().position(|b| *b == quote)?;
+            Some(&rest[1..end + 1])
+        }
+        _ => {
+            let end = rest
+                .iter()
+                .position(|b| b.is_ascii_whitespace() || *b == b';')
+                .unwrap_or(rest.len());
+            Some(&rest[..end])
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while bytes.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_detect_encoding() {
+        use super::*;
+        use encoding_rs::{GBK, SHIFT_JIS, UTF_8};
+
+        let detect = |html: &str| detect_encoding(html.as_bytes(), None);
+
+        let html = r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta charset="gb2312">
+            <title>Document</title>
+        </head>
+        </html>
+        "#;
+        assert_eq!(
+            detect(html),
+            DetectedEncoding::new(GBK, EncodingSource::Meta)
+        );
+
+        let html = r#"
+        <html lang="en">
+        <head>
+            <meta charset='gb2312'>
+        </head>
+        </html>
+        "#;
+        assert_eq!(detect(html).encoding, GBK);
+
+        let html = r#"
+        <head>
+            <meta http-equiv="Content-Type" content="text/html; charset=GB2312">
+        </head>
+        "#;
+        assert_eq!(detect(html).encoding, GBK);
+
+        let html = r#"
+        <head>
+            <meta content='text/html; charset=gbk' http-equiv="Content-Type">
+        </head>
+        "#;
+        assert_eq!(detect(html).encoding, GBK);
+
+        // content without http-equiv, declarations in comments and attribute values
+        let html = r#"
+        <!-- <meta charset="gbk"> -->
+        <div title='<meta charset="gbk">'></div>
+        <meta content="text/html; charset=gbk">
+        <meta name=x charset=shift_jis>
+        "#;
+        assert_eq!(detect(html).encoding, SHIFT_JIS);
+
+        let html = r#"<meta charset="utf-16le"><p>"#;
+        assert_eq!(detect(html).encoding, UTF_8);
+
+        let xml = r#"<?xml version="1.0" encoding='Shift_JIS'?><rss></rss>"#;
+        assert_eq!(
+            detect(xml),
+            DetectedEncoding::new(SHIFT_JIS, EncodingSource::XmlDeclaration)
+        );
+
+        // the byte order mark wins over the declarations
+        let bom = [&[0xEF, 0xBB, 0xBF][..], br#"<meta charset="gbk">"#].concat();
+        assert_eq!(
+            detect_encoding(&bom, Some("text/html; charset=gbk")),
+            DetectedEncoding::new(UTF_8, EncodingSource::Bom)
+        );
+        assert_eq!(
+            detect_encoding(br#"<meta charset="utf-8">"#, Some("text/html; charset=gbk")),
+            DetectedEncoding::new(GBK, EncodingSource::ContentType)
+        );
+
+        // declarations after the first 1024 bytes are ignored
+        let html = format!("{}<meta charset=gbk>", " ".repeat(PRESCAN_BYTES));
+        assert_ne!(detect(&html).source, EncodingSource::Meta);
+    }
+
+    #[test]
+    #[cfg(feature = "charset_detection")]
+    fn test_detect_statistically() {
+        use super::*;
+        use encoding_rs::GBK;
+
+        let (bytes, _, _) = GBK.encode(
+            "<p>这是一个没有声明编码的中文网页，需要根据字节的统计特征来猜测它的编码。</p>",
+        );
+        let detected = detect_encoding(&bytes, None);
+        assert_eq!(detected.source, EncodingSource::Detected);
+        assert_eq!(detected.encoding, GBK);
+    }
+}
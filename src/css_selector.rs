@@ -1,30 +1,222 @@
 //!  Select elements in HTML response using CSS selector
 //!
-use crate::error::Result;
+use crate::{
+    charset::detect_encoding,
+    error::{Result, ScraperError},
+    FromCssSelector,
+};
 use itertools::Itertools;
 use scraper::ElementRef;
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    ptr,
+    rc::Rc,
+    str::FromStr,
+    sync::{Arc, Weak},
+};
 
 /// Html Response
 pub struct Html {
     value: scraper::Html,
+    fragment: bool,
 }
 
 impl Html {
-    /// constructor
+    /// constructor, parse the HTML as a fragment, see [`Html::parse_fragment`]
     pub fn new(html_str: &str) -> Self {
+        Self::parse_fragment(html_str)
+    }
+
+    /// Parse a complete HTML document like a browser does,
+    /// `<html>`, `<head>` and `<body>` are kept as in the source, or created when they are omitted.
+    pub fn parse_document(html_str: &str) -> Self {
+        Self {
+            value: scraper::Html::parse_document(html_str),
+            fragment: false,
+        }
+    }
+
+    /// Parse an HTML fragment as the content of a `<body>` element,
+    /// the `<head>` and `<body>` tags are dropped and their content is placed directly under the root `<html>`.
+    pub fn parse_fragment(html_str: &str) -> Self {
         Self {
             value: scraper::Html::parse_fragment(html_str),
+            fragment: true,
         }
     }
+
+    /// Decode and parse the HTML document bytes,
+    /// the encoding is detected from the `Content-Type` header value and the document,
+    /// see [`crate::charset::detect_encoding`]
+    pub fn from_bytes(bytes: &[u8], content_type: Option<&str>) -> Self {
+        Self::parse_document(&detect_encoding(bytes, content_type).decode(bytes))
+    }
+
     /// Select elements in HTML using CSS selector
-    pub fn select(&self, selector: &str) -> Result<Selectable<scraper::Html>> {
+    pub fn select<S: IntoSelector>(&self, selector: S) -> Result<Selectable<'_, scraper::Html>> {
         Selectable::wrap(selector, &self.value)
     }
+
+    /// Returns the root element of the HTML
+    pub fn root_element(&self) -> SelectItem<'_> {
+        SelectItem::new(self.value.root_element())
+    }
+}
+
+thread_local! {
+    /// Snapshots parsed by this thread for [`HtmlSnapshot::with`], with their source
+    static PARSED: RefCell<Vec<(Weak<str>, Rc<Html>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Owned snapshot of a parsed [`Html`] that is `Send + Sync`,
+/// e.g. parsed in one task and shared by the threads of a rayon pool to extract elements concurrently.
+///
+/// The elements of a parsed document lazily cache their id and classes without synchronization,
+/// so it keeps the serialized document instead. [`HtmlSnapshot::with`] parses it once per thread
+/// and reuses the parsed [`Html`] until every clone of the snapshot is dropped,
+/// the selected elements only live during the call:
+/// ```
+/// use reqwest_scraper::css_selector::{Html, HtmlSnapshot};
+///
+/// let html = HtmlSnapshot::new(Html::parse_document("<ul><li>1</li><li>2</li></ul>"));
+/// let counts = std::thread::scope(|s| {
+///     let tasks = ["li", "ul"].map(|selector| {
+///         let html = &html;
+///         s.spawn(move || html.with(|html| html.select(selector).unwrap().iter().count()))
+///     });
+///     tasks.map(|task| task.join().unwrap())
+/// });
+/// assert_eq!(counts, [2, 1]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HtmlSnapshot {
+    source: Arc<str>,
+    fragment: bool,
+}
+
+impl HtmlSnapshot {
+    /// Take a snapshot of the parsed document
+    pub fn new(html: Html) -> Self {
+        let source = if html.fragment {
+            html.value.root_element().inner_html()
+        } else {
+            html.value.html()
+        };
+        Self {
+            source: source.into(),
+            fragment: html.fragment,
+        }
+    }
+
+    /// Access the snapshot parsed by the calling thread, it is only parsed on the first access
+    pub fn with<R, F: FnOnce(&Html) -> R>(&self, f: F) -> R {
+        let html = PARSED.with(|parsed| {
+            let mut parsed = parsed.borrow_mut();
+            // the documents of dropped snapshots are released
+            parsed.retain(|(source, _)| source.strong_count() > 0);
+            let cached = parsed
+                .iter()
+                .find(|(source, _)| ptr::addr_eq(source.as_ptr(), Arc::as_ptr(&self.source)));
+            if let Some((_, html)) = cached {
+                return html.clone();
+            }
+            let html = Rc::new(self.to_html());
+            parsed.push((Arc::downgrade(&self.source), html.clone()));
+            html
+        });
+        f(&html)
+    }
+
+    /// Parse a new [`Html`] owned by the caller on every call, as a document or a fragment like the original.
+    /// Prefer [`HtmlSnapshot::with`] to parse it once per thread
+    pub fn to_html(&self) -> Html {
+        if self.fragment {
+            Html::parse_fragment(&self.source)
+        } else {
+            Html::parse_document(&self.source)
+        }
+    }
+
+    /// Returns the serialized document
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl From<Html> for HtmlSnapshot {
+    fn from(html: Html) -> Self {
+        Self::new(html)
+    }
+}
+
+/// Compiled CSS selector.
+///
+/// Parse it once, e.g. in a `static`, and pass it to `select` to avoid re-parsing the selector string:
+/// ```
+/// use reqwest_scraper::css_selector::{Html, Selector};
+/// use std::sync::LazyLock;
+///
+/// static TITLE: LazyLock<Selector> = LazyLock::new(|| Selector::parse("h1.title").unwrap());
+///
+/// let html = Html::parse_fragment(r#"<h1 class="title">Hello</h1>"#);
+/// assert_eq!(html.select(&*TITLE).unwrap().first().unwrap().text(), "Hello");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Selector {
+    selector: Arc<scraper::Selector>,
+}
+
+impl Selector {
+    /// Parse the CSS selector
+    pub fn parse(selector: &str) -> Result<Self> {
+        Ok(Self {
+            selector: Arc::new(scraper::Selector::parse(selector)?),
+        })
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ScraperError;
+
+    fn from_str(selector: &str) -> Result<Self> {
+        Self::parse(selector)
+    }
+}
+
+/// CSS selector string or compiled [`Selector`] accepted by `select` and `matches`
+pub trait IntoSelector {
+    /// Returns the compiled selector
+    fn into_selector(self) -> Result<Selector>;
+}
+
+impl IntoSelector for &str {
+    fn into_selector(self) -> Result<Selector> {
+        Selector::parse(self)
+    }
+}
+
+impl IntoSelector for &String {
+    fn into_selector(self) -> Result<Selector> {
+        Selector::parse(self)
+    }
+}
+
+impl IntoSelector for Selector {
+    fn into_selector(self) -> Result<Selector> {
+        Ok(self)
+    }
+}
+
+impl IntoSelector for &Selector {
+    fn into_selector(self) -> Result<Selector> {
+        Ok(self.clone())
+    }
 }
 
 /// Wrapper object for HTML elements and CSS selectors
 pub struct Selectable<'a, T> {
-    selector: scraper::Selector,
+    selector: Arc<scraper::Selector>,
     node: &'a T,
 }
 
@@ -39,14 +231,15 @@ pub struct ElementSelectIterator<'a, 'b> {
 }
 
 /// HTML elements selected by CSS selector
+#[derive(Clone, Copy)]
 pub struct SelectItem<'a> {
     element: ElementRef<'a>,
 }
 
 impl<'a, T> Selectable<'a, T> {
-    fn wrap(selector: &str, html: &'a T) -> Result<Selectable<'a, T>> {
+    fn wrap<S: IntoSelector>(selector: S, html: &'a T) -> Result<Selectable<'a, T>> {
         Ok(Self {
-            selector: scraper::Selector::parse(selector)?,
+            selector: selector.into_selector()?.selector,
             node: html,
         })
     }
@@ -57,14 +250,14 @@ pub type SelectableHtml<'a> = Selectable<'a, scraper::Html>;
 
 impl<'a> Selectable<'a, scraper::Html> {
     /// iterator
-    pub fn iter(&self) -> HtmlSelectIterator {
+    pub fn iter(&self) -> HtmlSelectIterator<'_, '_> {
         HtmlSelectIterator {
             select: self.node.select(&self.selector),
         }
     }
 
     /// first match item
-    pub fn first(&self) -> Option<SelectItem> {
+    pub fn first(&self) -> Option<SelectItem<'_>> {
         self.iter().next()
     }
 }
@@ -74,14 +267,14 @@ pub type SelectableElement<'a> = Selectable<'a, ElementRef<'a>>;
 
 impl<'a> Selectable<'a, ElementRef<'a>> {
     /// iterator
-    pub fn iter(&self) -> ElementSelectIterator {
+    pub fn iter(&self) -> ElementSelectIterator<'_, '_> {
         ElementSelectIterator {
             select: self.node.select(&self.selector),
         }
     }
 
     /// first match item
-    pub fn first(&self) -> Option<SelectItem> {
+    pub fn first(&self) -> Option<SelectItem<'_>> {
         self.iter().next()
     }
 }
@@ -131,12 +324,12 @@ impl<'a> SelectItem<'a> {
     }
 
     /// Returns an iterator over the element's classes.
-    pub fn classes(&self) -> Classes {
+    pub fn classes(&self) -> Classes<'_> {
         self.element.value().classes()
     }
 
     /// Returns an iterator over the element's attributes.
-    pub fn attrs(&self) -> Attrs {
+    pub fn attrs(&self) -> Attrs<'_> {
         self.element.value().attrs()
     }
 
@@ -160,15 +353,49 @@ impl<'a> SelectItem<'a> {
         self.element.inner_html()
     }
 
+    /// Returns true if this element matches the CSS selector.
+    pub fn matches<S: IntoSelector>(&self, selector: S) -> Result<bool> {
+        Ok(selector.into_selector()?.selector.matches(&self.element))
+    }
+
     /// Iterate over all child nodes which are elements
     pub fn children(&self) -> impl Iterator<Item = SelectItem<'a>> {
         self.element.child_elements().map(SelectItem::new)
     }
 
     /// Use CSS selector to find elements based on the current element
-    pub fn select(&self, selector: &str) -> Result<Selectable<'a, ElementRef>> {
+    pub fn select<S: IntoSelector>(&self, selector: S) -> Result<Selectable<'a, ElementRef<'_>>> {
         Selectable::wrap(selector, &self.element)
     }
+
+    /// Extract the derived struct relative to the current element
+    pub fn extract<T: FromCssSelector>(&self) -> T::CssSelectorExtractResult {
+        T::from_select_item(self)
+    }
+}
+
+/// Parse the text extracted for a field of a `FromCssSelector` struct.
+///
+/// Used by the code generated from `#[derive(FromCssSelector)]`.
+#[doc(hidden)]
+pub fn parse_field<T>(
+    struct_name: &'static str,
+    field: &'static str,
+    selector: &'static str,
+    text: String,
+) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    text.parse::<T>()
+        .map_err(|err| ScraperError::CssSelectorParseError {
+            struct_name,
+            field,
+            selector,
+            message: err.to_string(),
+            text,
+        })
 }
 
 #[cfg(test)]
@@ -195,6 +422,8 @@ mod tests {
         assert!(content.is_some());
         let content = content.unwrap();
         assert_eq!(content.attr("id").unwrap(), "content");
+        assert!(content.matches("div#content").unwrap());
+        assert!(!content.matches("p").unwrap());
 
         let p1 = content.select("p:nth-child(1)").ok().unwrap();
         let p1 = p1.first();
@@ -206,4 +435,177 @@ mod tests {
         assert!(p2.is_some());
         assert_eq!(p2.unwrap().text(), "This is a test.");
     }
+
+    #[test]
+    fn test_parse_field() {
+        use super::*;
+
+        let price: f64 = parse_field("Item", "price", ".price", "12.5".into()).unwrap();
+        assert_eq!(price, 12.5);
+
+        let err = parse_field::<u32>("Item", "stars", ".stars", "five".into()).unwrap_err();
+        match err {
+            ScraperError::CssSelectorParseError {
+                struct_name,
+                field,
+                selector,
+                text,
+                ..
+            } => {
+                assert_eq!(struct_name, "Item");
+                assert_eq!(field, "stars");
+                assert_eq!(selector, ".stars");
+                assert_eq!(text, "five");
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        use super::*;
+        use encoding_rs::GBK;
+
+        let (bytes, _, _) = GBK.encode(r#"<meta charset="gbk"><p class="title">你好</p>"#);
+        let html = Html::from_bytes(&bytes, None);
+        assert_eq!(
+            html.select(".title").unwrap().first().unwrap().text(),
+            "你好"
+        );
+
+        let (bytes, _, _) = GBK.encode(r#"<p class="title">你好</p>"#);
+        let html = Html::from_bytes(&bytes, Some("text/html; charset=GBK"));
+        assert_eq!(
+            html.select(".title").unwrap().first().unwrap().text(),
+            "你好"
+        );
+    }
+
+    #[test]
+    fn test_parse_document() {
+        use super::*;
+
+        let html = r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <title>Document</title>
+            <meta name="description" content="regression">
+        </head>
+        <body class="home">
+            <p>hello</p>
+        </body>
+        </html>
+        "#;
+
+        let count = |html: &Html, selector: &str| html.select(selector).unwrap().iter().count();
+
+        let document = Html::parse_document(html);
+        assert_eq!(
+            document
+                .select("head > title")
+                .unwrap()
+                .first()
+                .unwrap()
+                .text(),
+            "Document"
+        );
+        assert_eq!(
+            document
+                .select("html[lang]")
+                .unwrap()
+                .first()
+                .unwrap()
+                .attr("lang"),
+            Some("en")
+        );
+        assert_eq!(count(&document, ":root"), 1);
+        assert_eq!(count(&document, ":root > head"), 1);
+        assert_eq!(count(&document, "html > body.home > p"), 1);
+        assert_eq!(
+            document
+                .select("head > meta[name=description]")
+                .unwrap()
+                .first()
+                .unwrap()
+                .attr("content"),
+            Some("regression")
+        );
+        assert_eq!(document.root_element().name(), "html");
+
+        // omitted tags are created like a browser does
+        let document = Html::parse_document("<title>Document</title><p>hello</p>");
+        assert_eq!(count(&document, "html > head > title"), 1);
+        assert_eq!(count(&document, "html > body > p"), 1);
+
+        // a fragment has no <head> and <body>
+        let fragment = Html::parse_fragment(html);
+        assert_eq!(count(&fragment, "head > title"), 0);
+        assert_eq!(count(&fragment, "body > p"), 0);
+        assert_eq!(count(&fragment, "html > p"), 1);
+
+        let fragment = Html::new("<li>1</li><li>2</li>");
+        assert_eq!(count(&fragment, "html > li"), 2);
+
+        let (bytes, _, _) = encoding_rs::UTF_8.encode(html);
+        assert_eq!(count(&Html::from_bytes(&bytes, None), "head > title"), 1);
+    }
+
+    #[test]
+    fn test_html_snapshot() {
+        use super::*;
+        use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+        assert_impl_all!(Html: Send);
+        assert_impl_all!(HtmlSnapshot: Send, Sync, Clone);
+        assert_not_impl_any!(Html: Sync);
+        assert_not_impl_any!(SelectItem<'static>: Send, Sync);
+
+        let html = HtmlSnapshot::new(Html::parse_document(
+            r#"<ul id="list"><li class="a b">1</li><li class="b">2</li><li id="last">3</li></ul>"#,
+        ));
+        let selectors = ["li.b", "#last", "ul#list > li", ".a.b", "li:nth-child(2)"];
+        let texts = std::thread::scope(|s| {
+            let tasks = selectors.map(|selector| {
+                let html = html.clone();
+                s.spawn(move || {
+                    html.with(|html| {
+                        html.select(selector)
+                            .unwrap()
+                            .iter()
+                            .map(|item| item.text())
+                            .join(",")
+                    })
+                })
+            });
+            tasks.map(|task| task.join().unwrap())
+        });
+        assert_eq!(texts, ["1,2", "3", "1,2,3", "1", "2"]);
+
+        // the snapshot is parsed once per thread and shared by the clones
+        let parsed = html.with(|html| html as *const Html);
+        assert_eq!(html.clone().with(|html| html as *const Html), parsed);
+        assert_eq!(
+            html.with(|html| html.select("li").unwrap().iter().count()),
+            3
+        );
+        // nested accesses on the same thread share the document too
+        assert!(html.with(|list| html.clone().with(|html| ptr::eq(list, html))));
+        drop(html);
+
+        let fragment = HtmlSnapshot::new(Html::parse_fragment(r#"<p>Tom &amp; Jerry</p>"#));
+        assert_eq!(fragment.as_str(), "<p>Tom &amp; Jerry</p>");
+        assert_eq!(
+            fragment.with(|html| html.select("p").unwrap().first().unwrap().inner_html()),
+            "Tom &amp; Jerry"
+        );
+        // the documents of dropped snapshots are released
+        assert_eq!(PARSED.with(|parsed| parsed.borrow().len()), 1);
+        let fragment = fragment.to_html();
+        assert!(fragment.select("body").unwrap().iter().next().is_none());
+        assert_eq!(
+            fragment.select("p").unwrap().first().unwrap().text(),
+            "Tom & Jerry"
+        );
+    }
 }
@@ -1,7 +1,16 @@
 //! Scraping Error
 //!
+use mime::Mime;
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER},
+    StatusCode,
+};
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
+/// Maximum length of the body text kept in [`ScraperError::HttpError`]
+pub const BODY_PREVIEW_LIMIT: usize = 2048;
+
 /// Scraping Error
 #[derive(Error, Debug)]
 pub enum ScraperError {
@@ -17,14 +26,56 @@ pub enum ScraperError {
 
     /// JsonPath Match Error
     #[cfg(feature = "jsonpath")]
+    #[deprecated(note = "a jsonpath that matches nothing is reported as `JsonPathNotFound`")]
     #[error("jsonpath match error:{0}")]
     JsonPathMatchError(String),
 
-    /// Json Deserialize Error
+    /// JsonPath matched nothing
+    #[cfg(feature = "jsonpath")]
+    #[error("the \"{path}\" jsonpath did not find data in json")]
+    JsonPathNotFound {
+        /// jsonpath that matched nothing
+        path: String,
+    },
+
+    /// JsonPath matched a value of another json type
     #[cfg(feature = "jsonpath")]
+    #[error("the \"{path}\" jsonpath matched {found}, expected {expected}")]
+    JsonPathTypeMismatch {
+        /// jsonpath of the value
+        path: String,
+        /// expected json type
+        expected: &'static str,
+        /// json type of the matched value
+        found: &'static str,
+    },
+
+    /// JMESPath compile or evaluate Error
+    #[cfg(feature = "jmespath")]
+    #[error(transparent)]
+    JmesPathError(#[from] jmespath::JmespathError),
+
+    /// Json Deserialize Error
+    #[cfg(any(feature = "jsonpath", feature = "jmespath"))]
     #[error(transparent)]
     JsonDeserializeError(#[from] serde_json::Error),
 
+    /// JsonPath field deserialize error
+    #[cfg(feature = "jsonpath")]
+    #[error("failed to deserialize field `{field}` of `{struct_name}` from {value} selected by \"{path}\": {message}")]
+    JsonPathParseError {
+        /// name of the derived struct
+        struct_name: &'static str,
+        /// name of the field
+        field: &'static str,
+        /// jsonpath of the field
+        path: &'static str,
+        /// JSON text of the value that failed to deserialize
+        value: String,
+        /// error message of the deserializer
+        message: String,
+    },
+
     /// Css Selector Error
     #[cfg(feature = "css_selector")]
     #[error("css selector error: {0}")]
@@ -35,7 +86,23 @@ pub enum ScraperError {
     #[error("css selector match error:{0}")]
     CssSelectorMatchError(String),
 
-    /// Html Document Parse Error
+    /// Css Selector field parse error
+    #[cfg(feature = "css_selector")]
+    #[error("failed to parse field `{field}` of `{struct_name}` from {text:?} selected by \"{selector}\": {message}")]
+    CssSelectorParseError {
+        /// name of the derived struct
+        struct_name: &'static str,
+        /// name of the field
+        field: &'static str,
+        /// css selector of the field
+        selector: &'static str,
+        /// raw text that failed to parse
+        text: String,
+        /// error message of the `FromStr` implementation
+        message: String,
+    },
+
+    /// Html or Xml Document Parse Error
     #[cfg(feature = "xpath")]
     #[error(transparent)]
     HtmlParseError(#[from] libxml::parser::XmlParseError),
@@ -45,6 +112,22 @@ pub enum ScraperError {
     #[error("{0}")]
     XPathError(String),
 
+    /// XPath field parse error
+    #[cfg(feature = "xpath")]
+    #[error("failed to parse field `{field}` of `{struct_name}` from {text:?} evaluated by \"{xpath}\": {message}")]
+    XPathParseError {
+        /// name of the derived struct
+        struct_name: &'static str,
+        /// name of the field
+        field: &'static str,
+        /// xpath expression of the field
+        xpath: &'static str,
+        /// raw text that failed to parse
+        text: String,
+        /// error message of the `FromStr` implementation
+        message: String,
+    },
+
     /// IO Error
     #[error(transparent)]
     IOError(#[from] reqwest::Error),
@@ -55,8 +138,79 @@ pub enum ScraperError {
     MiddlewareIOError(#[from] reqwest_middleware::Error),
 
     /// Http response failed
-    #[error("http request for \"{0}\" error code:{1}, body text:{2}")]
-    HttpError(String, u16, String),
+    #[error("http request for \"{url}\" error code:{status}, body text:{body}")]
+    HttpError {
+        /// url of the request
+        url: String,
+        /// response status code
+        status: StatusCode,
+        /// response headers
+        headers: Box<HeaderMap>,
+        /// beginning of the response body, at most [`BODY_PREVIEW_LIMIT`] bytes
+        body: String,
+    },
+
+    /// Http response failed with a JSON error body, see [`crate::status::StatusPolicy::json_error`]
+    #[cfg(feature = "json")]
+    #[error("http request for \"{url}\" error code:{status}, error:{message}")]
+    HttpJsonError {
+        /// url of the request
+        url: String,
+        /// response status code
+        status: StatusCode,
+        /// debug representation of the error
+        message: String,
+        /// deserialized error body
+        error: Box<dyn std::any::Any + Send + Sync>,
+    },
+}
+
+impl ScraperError {
+    /// Returns the response status code if this is an http error
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::HttpError { status, .. } => Some(*status),
+            #[cfg(feature = "json")]
+            Self::HttpJsonError { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Returns the response headers if this is a [`ScraperError::HttpError`]
+    pub fn headers(&self) -> Option<&HeaderMap> {
+        match self {
+            Self::HttpError { headers, .. } => Some(headers),
+            _ => None,
+        }
+    }
+
+    /// Returns the parsed `Retry-After` header of a [`ScraperError::HttpError`]
+    pub fn retry_after(&self) -> Option<RetryAfter> {
+        let value = self.headers()?.get(RETRY_AFTER)?.to_str().ok()?.trim();
+        match value.parse::<u64>() {
+            Ok(seconds) => Some(RetryAfter::Delay(Duration::from_secs(seconds))),
+            Err(_) => httpdate::parse_http_date(value).ok().map(RetryAfter::At),
+        }
+    }
+
+    /// Returns the parsed `Content-Type` header of a [`ScraperError::HttpError`]
+    pub fn content_type(&self) -> Option<Mime> {
+        self.headers()?
+            .get(CONTENT_TYPE)?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// Returns the deserialized error body if this is a [`ScraperError::HttpJsonError`] of type `E`
+    #[cfg(feature = "json")]
+    pub fn json_error<E: 'static>(&self) -> Option<&E> {
+        match self {
+            Self::HttpJsonError { error, .. } => error.downcast_ref(),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(feature = "css_selector")]
@@ -66,5 +220,95 @@ impl<'a> From<scraper::error::SelectorErrorKind<'a>> for ScraperError {
     }
 }
 
+/// Value of the `Retry-After` response header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryAfter {
+    /// Number of seconds to wait
+    Delay(Duration),
+    /// Date after which to retry
+    At(SystemTime),
+}
+
+impl RetryAfter {
+    /// Time to wait from `now` before retrying
+    pub fn delay_from(&self, now: SystemTime) -> Duration {
+        match self {
+            Self::Delay(delay) => *delay,
+            Self::At(at) => at.duration_since(now).unwrap_or_default(),
+        }
+    }
+}
+
+/// Truncate the body text to [`BODY_PREVIEW_LIMIT`] bytes on a char boundary
+pub(crate) fn body_preview(body: &[u8]) -> String {
+    let mut preview =
+        String::from_utf8_lossy(&body[..body.len().min(BODY_PREVIEW_LIMIT)]).into_owned();
+    if body.len() > BODY_PREVIEW_LIMIT {
+        // drop the replacement char of a multi-byte char cut in the middle
+        if preview.ends_with('\u{FFFD}') {
+            preview.pop();
+        }
+        preview.push_str("...");
+    }
+    preview
+}
+
 /// Result
 pub type Result<T> = std::result::Result<T, ScraperError>;
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_http_error() {
+        use super::*;
+        use reqwest::header::HeaderValue;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/json; charset=utf-8"),
+        );
+        let body = "中".repeat(BODY_PREVIEW_LIMIT);
+        let error = ScraperError::HttpError {
+            url: "http://example.com".into(),
+            status: StatusCode::TOO_MANY_REQUESTS,
+            headers: Box::new(headers),
+            body: body_preview(body.as_bytes()),
+        };
+        assert_eq!(error.status(), Some(StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(
+            error.retry_after(),
+            Some(RetryAfter::Delay(Duration::from_secs(120)))
+        );
+        assert_eq!(
+            error.content_type().unwrap().essence_str(),
+            "application/json"
+        );
+        assert!(matches!(error, ScraperError::HttpError { ref body, .. }
+            if body.len() <= BODY_PREVIEW_LIMIT + 3 && body.ends_with("中...")));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        let error = ScraperError::HttpError {
+            url: "http://example.com".into(),
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            headers: Box::new(headers),
+            body: String::new(),
+        };
+        let at = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(error.retry_after(), Some(RetryAfter::At(at)));
+        assert_eq!(
+            error
+                .retry_after()
+                .unwrap()
+                .delay_from(at - Duration::from_secs(5)),
+            Duration::from_secs(5)
+        );
+        assert!(error.content_type().is_none());
+    }
+}
@@ -0,0 +1,1069 @@
+//!  Extract elements from a streaming HTML response using a restricted CSS selector
+//!
+use crate::{
+    charset::{detect_encoding, PRESCAN_BYTES},
+    error::{Result, ScraperError},
+};
+use bytes::Bytes;
+use encoding_rs::Decoder;
+use futures_util::Stream;
+use markup5ever::data::{C1_REPLACEMENTS, NAMED_ENTITIES};
+use std::{
+    collections::VecDeque,
+    ops::Range,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Elements that never have an end tag
+const VOID_ELEMENTS: [&str; 18] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose content is not parsed as markup
+const RAW_TEXT_ELEMENTS: [&str; 7] = [
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+    "script",
+    "style",
+    "xmp",
+];
+
+/// Elements whose content is not parsed as markup, but whose character references are decoded
+const RCDATA_ELEMENTS: [&str; 2] = ["textarea", "title"];
+
+/// Restricted CSS selector that can be matched while the HTML is streamed.
+///
+/// Supports `*`, type, `#id`, `.class`, `[attr]`, `[attr=value]`, `[attr~=value]`,
+/// `[attr|=value]`, `[attr^=value]`, `[attr$=value]` and `[attr*=value]` selectors,
+/// combined with the descendant (` `) and child (`>`) combinators, and selector lists (`,`).
+#[derive(Debug, Clone)]
+pub struct StreamSelector {
+    selectors: Vec<ComplexSelector>,
+}
+
+/// Compound selectors from left to right, each with its combinator to the previous one
+#[derive(Debug, Clone)]
+struct ComplexSelector {
+    compounds: Vec<(Combinator, CompoundSelector)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Debug, Clone, Default)]
+struct CompoundSelector {
+    name: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<AttrSelector>,
+}
+
+#[derive(Debug, Clone)]
+struct AttrSelector {
+    name: String,
+    operator: AttrOperator,
+}
+
+#[derive(Debug, Clone)]
+enum AttrOperator {
+    Exists,
+    Equals(String),
+    Includes(String),
+    DashMatch(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+}
+
+impl StreamSelector {
+    /// Parse the restricted CSS selector
+    pub fn parse(selector: &str) -> Result<Self> {
+        let unsupported = |reason: &str| {
+            ScraperError::CssSelectorError(format!(
+                "unsupported stream selector `{}`: {}",
+                selector, reason
+            ))
+        };
+        let mut selectors = Vec::new();
+        let mut scanner = Scanner::new(selector);
+        loop {
+            let mut compounds = Vec::new();
+            let mut combinator = Combinator::Descendant;
+            loop {
+                scanner.skip_whitespace();
+                if scanner.eat('>') {
+                    if compounds.is_empty() || combinator == Combinator::Child {
+                        return Err(unsupported("misplaced `>` combinator"));
+                    }
+                    combinator = Combinator::Child;
+                    continue;
+                }
+                match scanner.peek() {
+                    None | Some(',') => break,
+                    _ => {}
+                }
+                let compound = scanner.compound().map_err(|reason| unsupported(&reason))?;
+                compounds.push((combinator, compound));
+                combinator = Combinator::Descendant;
+            }
+            if compounds.is_empty() || combinator == Combinator::Child {
+                return Err(unsupported("empty selector"));
+            }
+            selectors.push(ComplexSelector { compounds });
+            if !scanner.eat(',') {
+                break;
+            }
+        }
+        Ok(Self { selectors })
+    }
+
+    /// Check whether the innermost element of the stack matches the selector
+    fn matches(&self, stack: &[OpenElement]) -> bool {
+        !stack.is_empty()
+            && self
+                .selectors
+                .iter()
+                .any(|s| s.matches(s.compounds.len() - 1, stack, stack.len() - 1))
+    }
+}
+
+impl ComplexSelector {
+    fn matches(&self, index: usize, stack: &[OpenElement], pos: usize) -> bool {
+        let (combinator, compound) = &self.compounds[index];
+        if !compound.matches(&stack[pos]) {
+            return false;
+        }
+        if index == 0 {
+            return true;
+        }
+        match combinator {
+            Combinator::Child => pos > 0 && self.matches(index - 1, stack, pos - 1),
+            Combinator::Descendant => (0..pos).rev().any(|p| self.matches(index - 1, stack, p)),
+        }
+    }
+}
+
+impl CompoundSelector {
+    fn matches(&self, element: &OpenElement) -> bool {
+        if self.name.as_ref().is_some_and(|name| *name != element.name) {
+            return false;
+        }
+        if self
+            .id
+            .as_ref()
+            .is_some_and(|id| element.attr("id") != Some(id.as_str()))
+        {
+            return false;
+        }
+        if !self.classes.is_empty() {
+            let classes = element.attr("class").unwrap_or_default();
+            if !self
+                .classes
+                .iter()
+                .all(|class| classes.split_ascii_whitespace().any(|c| c == class))
+            {
+                return false;
+            }
+        }
+        self.attrs.iter().all(|attr| {
+            let Some(value) = element.attr(&attr.name) else {
+                return false;
+            };
+            match &attr.operator {
+                AttrOperator::Exists => true,
+                AttrOperator::Equals(v) => value == v,
+                AttrOperator::Includes(v) => value.split_ascii_whitespace().any(|w| w == v),
+                AttrOperator::DashMatch(v) => {
+                    value == v
+                        || value
+                            .strip_prefix(v.as_str())
+                            .is_some_and(|r| r.starts_with('-'))
+                }
+                AttrOperator::Prefix(v) => !v.is_empty() && value.starts_with(v.as_str()),
+                AttrOperator::Suffix(v) => !v.is_empty() && value.ends_with(v.as_str()),
+                AttrOperator::Contains(v) => !v.is_empty() && value.contains(v.as_str()),
+            }
+        })
+    }
+}
+
+/// Character scanner for the restricted selector syntax
+struct Scanner<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { rest: input }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()))
+            .unwrap_or(self.rest.len());
+        let (ident, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        (!ident.is_empty()).then_some(ident)
+    }
+
+    fn compound(&mut self) -> std::result::Result<CompoundSelector, String> {
+        let mut compound = CompoundSelector::default();
+        if !self.eat('*') {
+            compound.name = self.ident().map(|name| name.to_ascii_lowercase());
+        }
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.eat('#');
+                    let id = self.ident().ok_or("missing id after `#`")?;
+                    compound.id = Some(id.into());
+                }
+                Some('.') => {
+                    self.eat('.');
+                    let class = self.ident().ok_or("missing class name after `.`")?;
+                    compound.classes.push(class.into());
+                }
+                Some('[') => {
+                    self.eat('[');
+                    compound.attrs.push(self.attr()?);
+                }
+                None | Some(',') | Some('>') => break,
+                Some(c) if c.is_whitespace() => break,
+                Some(c) => return Err(format!("unexpected `{}`", c)),
+            }
+        }
+        Ok(compound)
+    }
+
+    fn attr(&mut self) -> std::result::Result<AttrSelector, String> {
+        self.skip_whitespace();
+        let name = self
+            .ident()
+            .ok_or("missing attribute name")?
+            .to_ascii_lowercase();
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(AttrSelector {
+                name,
+                operator: AttrOperator::Exists,
+            });
+        }
+        let operator: fn(String) -> AttrOperator = match self.peek() {
+            Some('=') => AttrOperator::Equals,
+            Some('~') => AttrOperator::Includes,
+            Some('|') => AttrOperator::DashMatch,
+            Some('^') => AttrOperator::Prefix,
+            Some('$') => AttrOperator::Suffix,
+            Some('*') => AttrOperator::Contains,
+            _ => return Err("unsupported attribute operator".into()),
+        };
+        if !self.eat('=') {
+            self.rest = &self.rest[1..];
+            if !self.eat('=') {
+                return Err("unsupported attribute operator".into());
+            }
+        }
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.eat(quote);
+                let end = self
+                    .rest
+                    .find(quote)
+                    .ok_or("unterminated attribute value")?;
+                let value = &self.rest[..end];
+                self.rest = &self.rest[end + 1..];
+                value
+            }
+            _ => self.ident().ok_or("missing attribute value")?,
+        };
+        self.skip_whitespace();
+        if !self.eat(']') {
+            return Err("expected `]`".into());
+        }
+        Ok(AttrSelector {
+            name,
+            operator: operator(value.into()),
+        })
+    }
+}
+
+/// Element that is open in the streamed document
+struct OpenElement {
+    name: String,
+    attrs: Vec<(String, String)>,
+}
+
+impl OpenElement {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Matched element whose end tag has not been reached yet
+struct Capture {
+    depth: usize,
+    html: String,
+    text: String,
+    inner_start: usize,
+}
+
+/// HTML element matched while streaming
+#[derive(Debug, Clone)]
+pub struct StreamElement {
+    name: String,
+    attrs: Vec<(String, String)>,
+    html: String,
+    inner: Range<usize>,
+    text: String,
+}
+
+impl StreamElement {
+    /// Returns the element name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the element ID.
+    pub fn id(&self) -> Option<&str> {
+        self.attr("id")
+    }
+
+    /// Returns true if element has the class.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.classes().any(|c| c == class)
+    }
+
+    /// Returns an iterator over the element's classes.
+    pub fn classes(&self) -> impl Iterator<Item = &str> {
+        self.attr("class")
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+    }
+
+    /// Returns an iterator over the element's attributes.
+    pub fn attrs(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.attrs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Returns the value of an attribute.
+    pub fn attr(&self, attr: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == attr)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the text of this element.
+    pub fn text(&self) -> String {
+        self.text.trim().into()
+    }
+
+    /// Returns the HTML of this element, as it appeared in the source.
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    /// Returns the inner HTML of this element, as it appeared in the source.
+    pub fn inner_html(&self) -> &str {
+        &self.html[self.inner.clone()]
+    }
+
+    /// Parse the HTML of this element to select its content with a full CSS selector
+    #[cfg(feature = "css_selector")]
+    pub fn to_html(&self) -> crate::css_selector::Html {
+        crate::css_selector::Html::new(&self.html)
+    }
+}
+
+/// Incremental HTML parser that emits the elements matching a [`StreamSelector`].
+///
+/// Only the open elements and the content of the matched elements are kept in memory,
+/// matches are emitted as soon as their end tag is reached.
+pub struct HtmlStreamParser {
+    selector: StreamSelector,
+    buf: String,
+    stack: Vec<OpenElement>,
+    captures: Vec<Capture>,
+    raw_text: Option<String>,
+    matches: VecDeque<StreamElement>,
+}
+
+impl HtmlStreamParser {
+    /// constructor
+    pub fn new(selector: StreamSelector) -> Self {
+        Self {
+            selector,
+            buf: String::new(),
+            stack: Vec::new(),
+            captures: Vec::new(),
+            raw_text: None,
+            matches: VecDeque::new(),
+        }
+    }
+
+    /// Feed the next chunk of decoded HTML
+    pub fn feed(&mut self, html: &str) {
+        self.buf.push_str(html);
+        self.process(false);
+    }
+
+    /// Signal the end of the document, unclosed matched elements are emitted
+    pub fn finish(&mut self) {
+        self.process(true);
+        while !self.stack.is_empty() {
+            self.pop(None);
+        }
+    }
+
+    /// Take the next matched element
+    pub fn next_match(&mut self) -> Option<StreamElement> {
+        self.matches.pop_front()
+    }
+
+    fn process(&mut self, eof: bool) {
+        let buf = std::mem::take(&mut self.buf);
+        let mut pos = 0;
+        while pos < buf.len() {
+            let rest = &buf[pos..];
+            if let Some(name) = &self.raw_text {
+                let end_tag = format!("</{}", name);
+                let raw = !RCDATA_ELEMENTS.contains(&name.as_str());
+                match find_end_tag(rest, &end_tag) {
+                    Some(0) => self.raw_text = None,
+                    Some(i) => {
+                        self.on_text(&rest[..i], raw);
+                        self.raw_text = None;
+                        pos += i;
+                        continue;
+                    }
+                    None if eof => {
+                        self.on_text(rest, raw);
+                        pos = buf.len();
+                        break;
+                    }
+                    None => {
+                        // keep enough to recognize an end tag split across chunks
+                        let mut keep =
+                            floor_char_boundary(rest, rest.len().saturating_sub(end_tag.len()));
+                        if !raw {
+                            keep = partial_entity_start(&rest[..keep]);
+                        }
+                        self.on_text(&rest[..keep], raw);
+                        pos += keep;
+                        break;
+                    }
+                }
+            }
+            match rest.find('<') {
+                Some(0) => {}
+                Some(i) => {
+                    self.on_text(&rest[..i], false);
+                    pos += i;
+                    continue;
+                }
+                None => {
+                    let keep = if eof {
+                        rest.len()
+                    } else {
+                        partial_entity_start(rest)
+                    };
+                    self.on_text(&rest[..keep], false);
+                    pos += keep;
+                    break;
+                }
+            }
+            match self.markup(rest) {
+                Some(consumed) => pos += consumed,
+                None if eof => {
+                    self.on_text(rest, false);
+                    pos = buf.len();
+                }
+                None => break,
+            }
+        }
+        self.buf = buf[pos..].to_string();
+    }
+
+    /// Handle the markup at the start of `rest`, returns `None` if it is incomplete
+    fn markup(&mut self, rest: &str) -> Option<usize> {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->")? + 7;
+            self.on_raw(&rest[..end]);
+            Some(end)
+        } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>")?;
+            self.on_raw(&rest[..9]);
+            self.on_text(&cdata[..end], true);
+            self.on_raw("]]>");
+            Some(end + 12)
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>')? + 1;
+            self.on_raw(&rest[..end]);
+            Some(end)
+        } else if let Some(tag) = rest.strip_prefix("</") {
+            let end = rest.find('>')? + 1;
+            if tag.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                let name_end = tag
+                    .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+                    .unwrap_or(tag.len());
+                self.on_end_tag(&tag[..name_end].to_ascii_lowercase(), &rest[..end]);
+            } else {
+                self.on_raw(&rest[..end]);
+            }
+            Some(end)
+        } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (tag, end) = parse_start_tag(rest)?;
+            self.on_start_tag(tag, &rest[..end]);
+            Some(end)
+        } else if rest.len() > 1 {
+            self.on_text("<", false);
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    fn on_start_tag(&mut self, tag: StartTag, source: &str) {
+        self.close_implied(&tag.name);
+        self.stack.push(OpenElement {
+            name: tag.name,
+            attrs: tag.attrs,
+        });
+        for capture in self.captures.iter_mut() {
+            capture.html.push_str(source);
+        }
+        if self.selector.matches(&self.stack) {
+            self.captures.push(Capture {
+                depth: self.stack.len(),
+                html: source.into(),
+                text: String::new(),
+                inner_start: source.len(),
+            });
+        }
+        let name = &self.stack[self.stack.len() - 1].name;
+        if tag.self_closing || VOID_ELEMENTS.contains(&name.as_str()) {
+            self.pop(None);
+        } else if RAW_TEXT_ELEMENTS.contains(&name.as_str())
+            || RCDATA_ELEMENTS.contains(&name.as_str())
+        {
+            self.raw_text = Some(name.clone());
+        }
+    }
+
+    fn on_end_tag(&mut self, name: &str, source: &str) {
+        match self.stack.iter().rposition(|e| e.name == name) {
+            Some(pos) => {
+                while self.stack.len() > pos + 1 {
+                    self.pop(None);
+                }
+                self.pop(Some(source));
+            }
+            None => self.on_raw(source),
+        }
+    }
+
+    /// Close the elements whose end tag is implied by the start of `name`
+    fn close_implied(&mut self, name: &str) {
+        let (closes, scope): (&[&str], &[&str]) = match name {
+            "li" => (&["li"], &["ul", "ol"]),
+            "dt" | "dd" => (&["dt", "dd"], &["dl"]),
+            "tr" => (&["tr"], &["table", "thead", "tbody", "tfoot"]),
+            "td" | "th" => (&["td", "th"], &["tr", "table"]),
+            "thead" | "tbody" | "tfoot" => (&["thead", "tbody", "tfoot"], &["table"]),
+            "option" => (&["option"], &["select", "datalist"]),
+            "address" | "article" | "aside" | "blockquote" | "details" | "div" | "dl"
+            | "fieldset" | "figure" | "footer" | "form" | "h1" | "h2" | "h3" | "h4" | "h5"
+            | "h6" | "header" | "hr" | "main" | "nav" | "ol" | "p" | "pre" | "section"
+            | "table" | "ul" => (&["p"], &["button", "table", "td", "th", "div"]),
+            _ => return,
+        };
+        for pos in (0..self.stack.len()).rev() {
+            let open = self.stack[pos].name.as_str();
+            if closes.contains(&open) {
+                while self.stack.len() > pos {
+                    self.pop(None);
+                }
+                return;
+            }
+            if scope.contains(&open) {
+                return;
+            }
+        }
+    }
+
+    /// Pop the innermost open element, `end_tag` is its source if it was closed explicitly
+    fn pop(&mut self, end_tag: Option<&str>) {
+        let depth = self.stack.len();
+        let Some(element) = self.stack.pop() else {
+            return;
+        };
+        if self.raw_text.as_deref() == Some(element.name.as_str()) {
+            self.raw_text = None;
+        }
+        let end_tag = end_tag.unwrap_or_default();
+        for capture in self.captures.iter_mut() {
+            capture.html.push_str(end_tag);
+        }
+        if self.captures.last().is_some_and(|c| c.depth == depth) {
+            let capture = self.captures.pop().unwrap();
+            let inner_end = capture.html.len() - end_tag.len();
+            self.matches.push_back(StreamElement {
+                name: element.name,
+                attrs: element.attrs,
+                inner: capture.inner_start..inner_end,
+                html: capture.html,
+                text: capture.text,
+            });
+        }
+    }
+
+    fn on_text(&mut self, source: &str, raw: bool) {
+        if self.captures.is_empty() || source.is_empty() {
+            return;
+        }
+        let text = if raw {
+            source.to_string()
+        } else {
+            decode_entities(source)
+        };
+        for capture in self.captures.iter_mut() {
+            capture.html.push_str(source);
+            capture.text.push_str(&text);
+        }
+    }
+
+    fn on_raw(&mut self, source: &str) {
+        for capture in self.captures.iter_mut() {
+            capture.html.push_str(source);
+        }
+    }
+}
+
+struct StartTag {
+    name: String,
+    attrs: Vec<(String, String)>,
+    self_closing: bool,
+}
+
+/// Parse the start tag at the beginning of `source`, returns `None` if it is incomplete
+fn parse_start_tag(source: &str) -> Option<(StartTag, usize)> {
+    let bytes = source.as_bytes();
+    let is_space = |b: u8| b.is_ascii_whitespace();
+    let mut i = 1;
+    while i < bytes.len() && !(is_space(bytes[i]) || bytes[i] == b'/' || bytes[i] == b'>') {
+        i += 1;
+    }
+    let name = source[1..i].to_ascii_lowercase();
+    let mut attrs: Vec<(String, String)> = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while i < bytes.len() && is_space(bytes[i]) {
+            i += 1;
+        }
+        match bytes.get(i)? {
+            b'>' => {
+                i += 1;
+                break;
+            }
+            b'/' => {
+                i += 1;
+                if *bytes.get(i)? == b'>' {
+                    self_closing = true;
+                    i += 1;
+                    break;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let start = i;
+        i += 1;
+        while i < bytes.len() && !(is_space(bytes[i]) || matches!(bytes[i], b'/' | b'>' | b'=')) {
+            i += 1;
+        }
+        let attr_name = source[start..i].to_ascii_lowercase();
+        while i < bytes.len() && is_space(bytes[i]) {
+            i += 1;
+        }
+        let mut value = "";
+        if *bytes.get(i)? == b'=' {
+            i += 1;
+            while i < bytes.len() && is_space(bytes[i]) {
+                i += 1;
+            }
+            match bytes.get(i)? {
+                quote @ (b'"' | b'\'') => {
+                    let end = source[i + 1..].find(*quote as char)? + i + 1;
+                    value = &source[i + 1..end];
+                    i = end + 1;
+                }
+                _ => {
+                    let start = i;
+                    while i < bytes.len() && !(is_space(bytes[i]) || bytes[i] == b'>') {
+                        i += 1;
+                    }
+                    value = &source[start..i];
+                }
+            }
+        }
+        if !attrs.iter().any(|(n, _)| *n == attr_name) {
+            attrs.push((attr_name, decode_entities(value)));
+        }
+    }
+    Some((
+        StartTag {
+            name,
+            attrs,
+            self_closing,
+        },
+        i,
+    ))
+}
+
+/// Decode the character references in text or attribute values
+fn decode_entities(source: &str) -> String {
+    let mut text = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(amp) = rest.find('&') {
+        text.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        match decode_entity(&rest[1..]) {
+            Some((decoded, consumed)) => {
+                text.push_str(&decoded);
+                rest = &rest[1 + consumed..];
+            }
+            None => {
+                text.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Decode the character reference following a `&`, returns the text and the consumed length
+fn decode_entity(source: &str) -> Option<(String, usize)> {
+    if let Some(number) = source.strip_prefix('#') {
+        let (digits, radix, prefix) = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 2),
+            None => (number, 10, 1),
+        };
+        let end = digits
+            .find(|c: char| !c.is_digit(radix))
+            .unwrap_or(digits.len());
+        if end == 0 {
+            return None;
+        }
+        let code = u32::from_str_radix(&digits[..end], radix).unwrap_or(u32::MAX);
+        let c = match code {
+            0x80..=0x9F => C1_REPLACEMENTS[(code - 0x80) as usize]
+                .unwrap_or_else(|| char::from_u32(code).unwrap_or('\u{FFFD}')),
+            0 => '\u{FFFD}',
+            _ => char::from_u32(code).unwrap_or('\u{FFFD}'),
+        };
+        let semicolon = digits[end..].starts_with(';') as usize;
+        return Some((c.to_string(), prefix + end + semicolon));
+    }
+    let end = source
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(source.len());
+    let name = &source[..end];
+    let (key, consumed) = if source[end..].starts_with(';') {
+        (&source[..end + 1], end + 1)
+    } else {
+        (name, end)
+    };
+    match NAMED_ENTITIES.get(key) {
+        Some(&(c1, c2)) if c1 != 0 => {
+            let decoded = [c1, c2]
+                .into_iter()
+                .filter(|c| *c != 0)
+                .filter_map(char::from_u32)
+                .collect();
+            Some((decoded, consumed))
+        }
+        _ => None,
+    }
+}
+
+/// Position of a character reference that may continue in the next chunk
+fn partial_entity_start(text: &str) -> usize {
+    match text.rfind('&') {
+        Some(amp)
+            if text.len() - amp <= 32
+                && !text[amp..].contains(|c: char| c == ';' || c.is_whitespace()) =>
+        {
+            amp
+        }
+        _ => text.len(),
+    }
+}
+
+/// Position of the `end_tag`, e.g. `</script`, closing a raw text element.
+/// Its name must be followed by whitespace, `/` or `>`, `None` if it may be completed by the next chunk
+fn find_end_tag(text: &str, end_tag: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(i) = find_ignore_ascii_case(&text[from..], end_tag) {
+        let start = from + i;
+        match text.as_bytes().get(start + end_tag.len()) {
+            Some(b) if b.is_ascii_whitespace() || *b == b'/' || *b == b'>' => return Some(start),
+            Some(_) => from = start + 1,
+            None => return None,
+        }
+    }
+    None
+}
+
+fn find_ignore_ascii_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Stream of the elements matching a [`StreamSelector`] in a streamed HTML response body
+pub struct HtmlElementStream {
+    body: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+    content_type: Option<String>,
+    decoder: Option<Decoder>,
+    prescan: Vec<u8>,
+    parser: HtmlStreamParser,
+    finished: bool,
+}
+
+impl HtmlElementStream {
+    /// constructor, the encoding is detected from the `content_type` header value
+    /// and the beginning of the body, see [`crate::charset::detect_encoding`]
+    pub fn new<S>(body: S, selector: StreamSelector, content_type: Option<String>) -> Self
+    where
+        S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    {
+        Self {
+            body: Box::pin(body),
+            content_type,
+            decoder: None,
+            prescan: Vec::new(),
+            parser: HtmlStreamParser::new(selector),
+            finished: false,
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8], last: bool) {
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => {
+                self.prescan.extend_from_slice(bytes);
+                if self.prescan.len() < PRESCAN_BYTES && !last {
+                    return;
+                }
+                let detected = detect_encoding(&self.prescan, self.content_type.as_deref());
+                let mut decoder = detected.encoding.new_decoder();
+                let prescan = std::mem::take(&mut self.prescan);
+                self.parser.feed(&decode(&mut decoder, &prescan, last));
+                self.decoder = Some(decoder);
+                return;
+            }
+        };
+        let text = decode(decoder, bytes, last);
+        self.parser.feed(&text);
+    }
+}
+
+fn decode(decoder: &mut Decoder, bytes: &[u8], last: bool) -> String {
+    let capacity = decoder
+        .max_utf8_buffer_length(bytes.len())
+        .unwrap_or(bytes.len() * 3 + 16);
+    let mut text = String::with_capacity(capacity);
+    let _ = decoder.decode_to_string(bytes, &mut text, last);
+    text
+}
+
+impl Stream for HtmlElementStream {
+    type Item = Result<StreamElement>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(element) = this.parser.next_match() {
+                return Poll::Ready(Some(Ok(element)));
+            }
+            if this.finished {
+                return Poll::Ready(None);
+            }
+            match this.body.as_mut().poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(chunk))) => this.feed(&chunk, false),
+                Poll::Ready(Some(Err(err))) => {
+                    this.finished = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                Poll::Ready(None) => {
+                    this.feed(&[], true);
+                    this.parser.finish();
+                    this.finished = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_stream_selector() {
+        use super::*;
+
+        let html = r#"
+        <html>
+            <body>
+                <ul id="list" class="items">
+                    <li class="item" data-id="1"><a href="/a?x=1&amp;y=2">Tom &amp; Jerry</a>
+                    <li class="item sold-out" data-id="2"><a href='/b'>Caf&eacute;</a><br></li>
+                    <li class="ad"><script>if (a < b) { document.write("<li class='item'>") }</script></li>
+                </ul>
+                <!-- <li class="item">comment</li> -->
+                <p>x < y</p>
+            </body>
+        </html>
+        "#;
+
+        // feed the document in every chunk size to exercise the chunk boundaries
+        for chunk_size in [1, 2, 3, 7, 64, html.len()] {
+            let selector = StreamSelector::parse("ul#list > li.item, p").unwrap();
+            let mut parser = HtmlStreamParser::new(selector);
+            let mut chunk = String::new();
+            for c in html.chars() {
+                chunk.push(c);
+                if chunk.len() >= chunk_size {
+                    parser.feed(&chunk);
+                    chunk.clear();
+                }
+            }
+            parser.feed(&chunk);
+            parser.finish();
+
+            let first = parser.next_match().unwrap();
+            assert_eq!(first.name(), "li");
+            assert_eq!(first.attr("data-id"), Some("1"));
+            assert_eq!(first.text(), "Tom & Jerry");
+            assert_eq!(
+                first.inner_html().trim(),
+                r#"<a href="/a?x=1&amp;y=2">Tom &amp; Jerry</a>"#
+            );
+
+            let second = parser.next_match().unwrap();
+            assert_eq!(second.attr("data-id"), Some("2"));
+            assert!(second.has_class("sold-out"));
+            assert_eq!(second.text(), "Café");
+            assert_eq!(
+                second.html(),
+                r#"<li class="item sold-out" data-id="2"><a href='/b'>Caf&eacute;</a><br></li>"#
+            );
+
+            let third = parser.next_match().unwrap();
+            assert_eq!(third.name(), "p");
+            assert_eq!(third.text(), "x < y");
+            assert!(parser.next_match().is_none());
+        }
+    }
+
+    #[test]
+    fn test_raw_text() {
+        use super::*;
+
+        let html = r#"
+        <head><title>Tom &amp; Jerry <b></title></head>
+        <body>
+            <script>var t = "</scriptx>"; var s = "<p>";</script >
+            <textarea name="note">a &lt; b</textarea/>
+            <p>after</p>
+            <style>p::after { content: "</p>" }</style>
+        </body>
+        "#;
+
+        for chunk_size in [1, 2, 3, 7, 64, html.len()] {
+            let selector = StreamSelector::parse("title, script, textarea, p").unwrap();
+            let mut parser = HtmlStreamParser::new(selector);
+            let mut chunk = String::new();
+            for c in html.chars() {
+                chunk.push(c);
+                if chunk.len() >= chunk_size {
+                    parser.feed(&chunk);
+                    chunk.clear();
+                }
+            }
+            parser.feed(&chunk);
+            parser.finish();
+
+            // title and textarea decode their character references, but don't parse markup
+            let title = parser.next_match().unwrap();
+            assert_eq!(title.name(), "title");
+            assert_eq!(title.text(), "Tom & Jerry <b>");
+            assert_eq!(title.inner_html(), "Tom &amp; Jerry <b>");
+
+            // `</scriptx` doesn't close the script
+            let script = parser.next_match().unwrap();
+            assert_eq!(script.name(), "script");
+            assert_eq!(script.text(), r#"var t = "</scriptx>"; var s = "<p>";"#);
+
+            let textarea = parser.next_match().unwrap();
+            assert_eq!(textarea.name(), "textarea");
+            assert_eq!(textarea.text(), "a < b");
+
+            let p = parser.next_match().unwrap();
+            assert_eq!(p.name(), "p");
+            assert_eq!(p.text(), "after");
+            assert!(parser.next_match().is_none());
+        }
+    }
+
+    #[test]
+    fn test_unsupported_stream_selector() {
+        use super::*;
+
+        assert!(StreamSelector::parse("div > a[href^='https'], .x .y").is_ok());
+        assert!(StreamSelector::parse("li:first-child").is_err());
+        assert!(StreamSelector::parse("h1 + p").is_err());
+        assert!(StreamSelector::parse("> p").is_err());
+        assert!(StreamSelector::parse("").is_err());
+    }
+}
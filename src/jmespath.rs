@@ -0,0 +1,222 @@
+//!  Use JMESPath to query json response
+//!
+use crate::error::{Result, ScraperError};
+use jmespath::{Context, Expression, Rcvar, Variable, DEFAULT_RUNTIME};
+use serde::de::DeserializeOwned;
+pub use serde_json::Value;
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// Json Response queried with [JMESPath](https://jmespath.org) expressions
+/// ```
+/// use reqwest_scraper::jmespath::JmesJson;
+///
+/// let json = JmesJson::new(r#"{"items":[{"name":"a","price":8},{"name":"b","price":12}]}"#).unwrap();
+/// let names: Vec<String> = json.search_as("items[?price > `10`].name").unwrap();
+/// assert_eq!(names, ["b"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct JmesJson {
+    data: Rcvar,
+}
+
+impl JmesJson {
+    /// constructor
+    pub fn new(json: &str) -> Result<Self> {
+        let data: Variable = serde_json::from_str(json)?;
+        Ok(Self {
+            data: Rcvar::new(data),
+        })
+    }
+
+    /// Convert the parsed json value
+    pub fn from_value(value: &Value) -> Result<Self> {
+        Ok(Self {
+            data: Rcvar::new(Variable::try_from(value)?),
+        })
+    }
+
+    /// Use the JMESPath expression to query the json,
+    /// an expression that matches nothing returns `null`
+    pub fn search<E: IntoJmesPath>(&self, expression: E) -> Result<Value> {
+        let result = expression.into_jmespath()?.search(&self.data)?;
+        Ok(serde_json::to_value(&*result)?)
+    }
+
+    /// Use the JMESPath expression to query the json and convert the result into structures
+    pub fn search_as<T: DeserializeOwned, E: IntoJmesPath>(&self, expression: E) -> Result<T> {
+        Ok(serde_json::from_value(self.search(expression)?)?)
+    }
+}
+
+/// Compiled JMESPath expression, reusable across documents.
+///
+/// Compile it once, e.g. in a `static`, and pass it to `search` to avoid re-parsing the expression:
+/// ```
+/// use reqwest_scraper::jmespath::{JmesJson, JmesPath};
+/// use std::sync::LazyLock;
+///
+/// static NAMES: LazyLock<JmesPath> = LazyLock::new(|| JmesPath::compile("items[].name").unwrap());
+///
+/// let json = JmesJson::new(r#"{"items":[{"name":"a"},{"name":"b"}]}"#).unwrap();
+/// assert_eq!(json.search_as::<Vec<String>, _>(&*NAMES).unwrap(), ["a", "b"]);
+/// ```
+#[derive(Clone)]
+pub struct JmesPath {
+    expression: Arc<Expression<'static>>,
+}
+
+impl JmesPath {
+    /// Compile the JMESPath expression
+    pub fn compile(expression: &str) -> Result<Self> {
+        Ok(Self {
+            expression: Arc::new(jmespath::compile(expression)?),
+        })
+    }
+
+    /// Returns the JMESPath expression
+    pub fn as_str(&self) -> &str {
+        self.expression.as_str()
+    }
+
+    /// The data is interpreted directly, `Expression::search` would serialize it again
+    fn search(&self, data: &Rcvar) -> Result<Rcvar> {
+        let mut context = Context::new(self.as_str(), &DEFAULT_RUNTIME);
+        Ok(jmespath::interpret(
+            data,
+            self.expression.as_ast(),
+            &mut context,
+        )?)
+    }
+}
+
+impl fmt::Debug for JmesPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("JmesPath").field(&self.as_str()).finish()
+    }
+}
+
+impl FromStr for JmesPath {
+    type Err = ScraperError;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        Self::compile(expression)
+    }
+}
+
+/// JMESPath string or compiled [`JmesPath`] accepted by the `search` methods of [`JmesJson`]
+pub trait IntoJmesPath {
+    /// Returns the compiled JMESPath expression
+    fn into_jmespath(self) -> Result<JmesPath>;
+}
+
+impl IntoJmesPath for &str {
+    fn into_jmespath(self) -> Result<JmesPath> {
+        JmesPath::compile(self)
+    }
+}
+
+impl IntoJmesPath for &String {
+    fn into_jmespath(self) -> Result<JmesPath> {
+        JmesPath::compile(self)
+    }
+}
+
+impl IntoJmesPath for JmesPath {
+    fn into_jmespath(self) -> Result<JmesPath> {
+        Ok(self)
+    }
+}
+
+impl IntoJmesPath for &JmesPath {
+    fn into_jmespath(self) -> Result<JmesPath> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_jmespath() {
+        use super::*;
+        use serde::Deserialize;
+
+        let json = r#"{
+            "reservations": [
+                {"instances": [{"id": "i-1", "state": "running", "cpu": 2},
+                               {"id": "i-2", "state": "stopped", "cpu": 4}]},
+                {"instances": [{"id": "i-3", "state": "running", "cpu": 8}]}
+            ]
+        }"#;
+        let json = JmesJson::new(json).unwrap();
+
+        let ids: Vec<String> = json
+            .search_as("reservations[].instances[?state == 'running'].id[]")
+            .unwrap();
+        assert_eq!(ids, ["i-1", "i-3"]);
+
+        // JMESPath functions compute numbers as floats
+        let total: f64 = json
+            .search_as("sum(reservations[].instances[].cpu)")
+            .unwrap();
+        assert_eq!(total, 14.0);
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Instance {
+            id: String,
+            cpu: u32,
+        }
+        let instances: Vec<Instance> = json
+            .search_as("reservations[].instances[] | [?cpu > `2`].{id: id, cpu: cpu}")
+            .unwrap();
+        assert_eq!(
+            instances,
+            [
+                Instance {
+                    id: "i-2".into(),
+                    cpu: 4
+                },
+                Instance {
+                    id: "i-3".into(),
+                    cpu: 8
+                },
+            ]
+        );
+
+        assert_eq!(
+            json.search("reservations[0].instances[0]").unwrap(),
+            serde_json::json!({"id": "i-1", "state": "running", "cpu": 2})
+        );
+        assert_eq!(json.search("missing.field").unwrap(), Value::Null);
+        assert!(json.search_as::<String, _>("missing.field").is_err());
+    }
+
+    #[test]
+    fn test_compiled_jmespath() {
+        use super::*;
+
+        static_assertions::assert_impl_all!(JmesJson: Send, Sync);
+        static_assertions::assert_impl_all!(JmesPath: Send, Sync);
+
+        let names: JmesPath = "items[].name".parse().unwrap();
+        assert_eq!(names.as_str(), "items[].name");
+
+        let value = serde_json::json!({"items": [{"name": "a"}, {"name": "b"}]});
+        let json = JmesJson::from_value(&value).unwrap();
+        assert_eq!(
+            json.search_as::<Vec<String>, _>(&names).unwrap(),
+            ["a", "b"]
+        );
+        let json = JmesJson::new(r#"{"items":[{"name":"c"}]}"#).unwrap();
+        assert_eq!(json.search_as::<Vec<String>, _>(names).unwrap(), ["c"]);
+
+        assert!(matches!(
+            JmesPath::compile("items[?"),
+            Err(ScraperError::JmesPathError(_))
+        ));
+        assert!(matches!(
+            json.search("abs(items)"),
+            Err(ScraperError::JmesPathError(_))
+        ));
+    }
+}
@@ -0,0 +1,550 @@
+//!  Deserialize the values matched by a simple jsonpath from a streaming JSON response
+//!
+use crate::error::{Result, ScraperError};
+use bytes::Bytes;
+use futures_util::Stream;
+use jsonpath_lib::JsonPathError;
+use serde::de::{DeserializeOwned, Error as _, IgnoredAny};
+use std::{
+    collections::VecDeque,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Simple jsonpath that can be matched while the JSON is streamed.
+///
+/// Supports the root `$` followed by member names (`.name`, `['name']`, `["name"]`),
+/// array indexes (`[0]`) and wildcards (`.*`, `[*]`), e.g. `$.data[*]` or `$.pages[*].items[*]`.
+#[derive(Debug, Clone)]
+pub struct StreamJsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl StreamJsonPath {
+    /// Parse the jsonpath, an error is returned for unsupported syntax
+    pub fn parse(path: &str) -> Result<Self> {
+        let error = |message: &str| -> ScraperError {
+            JsonPathError::Path(format!("unsupported stream jsonpath `{path}`: {message}")).into()
+        };
+        let mut rest = path
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| error("expected `$`"))?;
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                let name = &after[..end];
+                segments.push(match name {
+                    "" => return Err(error("expected a member name after `.`")),
+                    "*" => Segment::Wildcard,
+                    name => Segment::Key(name.to_string()),
+                });
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let (segment, after) = match after.chars().next() {
+                    Some(quote @ ('\'' | '"')) => parse_quoted(&after[1..], quote)
+                        .map(|(name, after)| (Segment::Key(name), after))
+                        .ok_or_else(|| error("unterminated member name"))?,
+                    _ => {
+                        let end = after.find(']').ok_or_else(|| error("expected `]`"))?;
+                        let segment =
+                            match after[..end].trim() {
+                                "*" => Segment::Wildcard,
+                                index => Segment::Index(index.parse().map_err(|_| {
+                                    error("expected an index, `*` or a quoted name")
+                                })?),
+                            };
+                        (segment, &after[end..])
+                    }
+                };
+                segments.push(segment);
+                rest = after
+                    .trim_start()
+                    .strip_prefix(']')
+                    .ok_or_else(|| error("expected `]`"))?;
+            } else {
+                return Err(error("expected `.` or `[`"));
+            }
+        }
+        Ok(Self { segments })
+    }
+
+    /// Whether the value at the position of the open containers is matched
+    fn matches(&self, stack: &[Frame]) -> bool {
+        self.segments.len() == stack.len()
+            && self
+                .segments
+                .iter()
+                .zip(stack)
+                .all(|(segment, frame)| match (segment, frame) {
+                    (Segment::Wildcard, _) => true,
+                    (Segment::Key(name), Frame::Object(Some(key))) => name == key,
+                    (Segment::Index(index), Frame::Array(i)) => index == i,
+                    _ => false,
+                })
+    }
+}
+
+/// Member name up to the closing quote, with `\'`, `\"` and `\\` escapes
+fn parse_quoted(input: &str, quote: char) -> Option<(String, &str)> {
+    let mut name = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => name.push(chars.next()?.1),
+            c if c == quote => return Some((name, &input[i + 1..])),
+            c => name.push(c),
+        }
+    }
+    None
+}
+
+/// Open container, with the member name or the index of its current value
+#[derive(Debug)]
+enum Frame {
+    /// the member name is only kept while it can be matched by the jsonpath
+    Object(Option<String>),
+    Array(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Value,
+    ArrayValueOrEnd,
+    ObjectKeyOrEnd,
+    ObjectKey,
+    Colon,
+    CommaOrEnd,
+    Done,
+}
+
+/// Incremental JSON parser that emits the values matching a [`StreamJsonPath`].
+///
+/// Only the open containers and the text of the matched value are kept in memory,
+/// matches are emitted as soon as the value is complete.
+pub struct JsonStreamParser {
+    path: StreamJsonPath,
+    buf: Vec<u8>,
+    /// position of `buf` in the document
+    offset: usize,
+    /// position of the next token in `buf`
+    pos: usize,
+    stack: Vec<Frame>,
+    state: State,
+    /// start of the matched value in `buf` and the number of its enclosing containers
+    capture: Option<(usize, usize)>,
+    matches: VecDeque<Vec<u8>>,
+}
+
+impl JsonStreamParser {
+    /// constructor
+    pub fn new(path: StreamJsonPath) -> Self {
+        Self {
+            path,
+            buf: Vec::new(),
+            offset: 0,
+            pos: 0,
+            stack: Vec::new(),
+            state: State::Value,
+            capture: None,
+            matches: VecDeque::new(),
+        }
+    }
+
+    /// Feed the next chunk of the JSON document
+    pub fn feed(&mut self, json: &[u8]) -> Result<()> {
+        self.buf.extend_from_slice(json);
+        self.process(false)
+    }
+
+    /// Signal the end of the document, an error is returned if it is incomplete
+    pub fn finish(&mut self) -> Result<()> {
+        self.process(true)?;
+        if self.state != State::Done {
+            return Err(self.error("unexpected end of json", self.buf.len()));
+        }
+        Ok(())
+    }
+
+    /// Take the JSON text of the next matched value
+    pub fn next_match(&mut self) -> Option<Vec<u8>> {
+        self.matches.pop_front()
+    }
+
+    fn process(&mut self, eof: bool) -> Result<()> {
+        if self.offset == 0 && self.pos == 0 {
+            if self.buf.starts_with(UTF8_BOM) {
+                self.buf.drain(..UTF8_BOM.len());
+                self.offset = UTF8_BOM.len();
+            } else if UTF8_BOM.starts_with(&self.buf) && !eof {
+                // wait for the rest of a possible byte order mark
+                return Ok(());
+            }
+        }
+        let buf = std::mem::take(&mut self.buf);
+        let result = self.tokenize(&buf, eof);
+        self.buf = buf;
+        let pos = result?;
+        // the consumed input is dropped, except the text of a value being matched
+        let keep = self.capture.map_or(pos, |(start, _)| start.min(pos));
+        self.buf.drain(..keep);
+        self.offset += keep;
+        self.pos = pos - keep;
+        if let Some((start, _)) = &mut self.capture {
+            *start -= keep;
+        }
+        Ok(())
+    }
+
+    /// Consume the complete tokens of `buf`, returns the position of the first unconsumed byte
+    fn tokenize(&mut self, buf: &[u8], eof: bool) -> Result<usize> {
+        let mut pos = self.pos;
+        loop {
+            while pos < buf.len() && matches!(buf[pos], b' ' | b'\t' | b'\n' | b'\r') {
+                pos += 1;
+            }
+            let Some(&c) = buf.get(pos) else {
+                return Ok(pos);
+            };
+            match self.state {
+                State::Done => return Err(self.error("trailing characters", pos)),
+                State::Colon if c == b':' => {
+                    pos += 1;
+                    self.state = State::Value;
+                }
+                State::Colon => return Err(self.error("expected `:`", pos)),
+                State::CommaOrEnd => match (c, self.stack.last_mut()) {
+                    (b',', Some(Frame::Array(index))) => {
+                        *index += 1;
+                        pos += 1;
+                        self.state = State::Value;
+                    }
+                    (b',', Some(Frame::Object(_))) => {
+                        pos += 1;
+                        self.state = State::ObjectKey;
+                    }
+                    (b']', Some(Frame::Array(_))) | (b'}', Some(Frame::Object(_))) => {
+                        pos += 1;
+                        self.end_container(buf, pos);
+                    }
+                    _ => return Err(self.error("expected `,` or the end of the container", pos)),
+                },
+                State::ObjectKeyOrEnd if c == b'}' => {
+                    pos += 1;
+                    self.end_container(buf, pos);
+                }
+                State::ObjectKeyOrEnd | State::ObjectKey if c == b'"' => {
+                    let Some(end) = string_end(buf, pos) else {
+                        return Ok(pos);
+                    };
+                    let key = if self.stack.len() <= self.path.segments.len() {
+                        let key = serde_json::from_slice(&buf[pos..end])
+                            .map_err(|_| self.error("invalid member name", pos))?;
+                        Some(key)
+                    } else {
+                        self.validate(&buf[pos..end], pos, "invalid member name")?;
+                        None
+                    };
+                    if let Some(frame) = self.stack.last_mut() {
+                        *frame = Frame::Object(key);
+                    }
+                    pos = end;
+                    self.state = State::Colon;
+                }
+                State::ObjectKeyOrEnd | State::ObjectKey => {
+                    return Err(self.error("expected a member name", pos))
+                }
+                State::ArrayValueOrEnd if c == b']' => {
+                    pos += 1;
+                    self.end_container(buf, pos);
+                }
+                State::Value | State::ArrayValueOrEnd => {
+                    if self.capture.is_none() && self.path.matches(&self.stack) {
+                        self.capture = Some((pos, self.stack.len()));
+                    }
+                    match c {
+                        b'{' => {
+                            pos += 1;
+                            self.stack.push(Frame::Object(None));
+                            self.state = State::ObjectKeyOrEnd;
+                        }
+                        b'[' => {
+                            pos += 1;
+                            self.stack.push(Frame::Array(0));
+                            self.state = State::ArrayValueOrEnd;
+                        }
+                        b'"' => {
+                            let Some(end) = string_end(buf, pos) else {
+                                return Ok(pos);
+                            };
+                            self.validate(&buf[pos..end], pos, "invalid string")?;
+                            pos = end;
+                            self.end_value(buf, pos);
+                        }
+                        b'-' | b'0'..=b'9' | b't' | b'f' | b'n' => {
+                            let end = buf[pos..]
+                                .iter()
+                                .position(|b| !matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'+' | b'-' | b'.' | b'E'))
+                                .map_or(buf.len(), |len| pos + len);
+                            if end == buf.len() && !eof {
+                                // the number or literal may continue in the next chunk
+                                return Ok(pos);
+                            }
+                            self.validate(&buf[pos..end], pos, "invalid number or literal")?;
+                            pos = end;
+                            self.end_value(buf, pos);
+                        }
+                        _ => return Err(self.error("expected a value", pos)),
+                    }
+                }
+            }
+        }
+    }
+
+    fn end_container(&mut self, buf: &[u8], end: usize) {
+        self.stack.pop();
+        self.end_value(buf, end);
+    }
+
+    /// The value ending at `end` is complete, it is emitted if it was matched
+    fn end_value(&mut self, buf: &[u8], end: usize) {
+        if let Some((start, depth)) = self.capture {
+            if depth == self.stack.len() {
+                self.matches.push_back(buf[start..end].to_vec());
+                self.capture = None;
+            }
+        }
+        self.state = if self.stack.is_empty() {
+            State::Done
+        } else {
+            State::CommaOrEnd
+        };
+    }
+
+    /// Check the string, number or literal token starting at `pos`,
+    /// tokens of unmatched values are skipped and would not be validated by the deserializer
+    fn validate(&self, token: &[u8], pos: usize, message: &str) -> Result<()> {
+        match std::str::from_utf8(token) {
+            Ok(_) if serde_json::from_slice::<IgnoredAny>(token).is_ok() => Ok(()),
+            _ => Err(self.error(message, pos)),
+        }
+    }
+
+    fn error(&self, message: &str, pos: usize) -> ScraperError {
+        ScraperError::JsonDeserializeError(serde_json::Error::custom(format!(
+            "{message} at byte {}",
+            self.offset + pos
+        )))
+    }
+}
+
+/// End of the string starting at `start`, `None` if the closing quote isn't buffered yet
+fn string_end(buf: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < buf.len() {
+        match buf[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Stream of the values matching a [`StreamJsonPath`] in a streamed JSON response body,
+/// deserialized into `T`
+pub struct JsonItemStream<T> {
+    body: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+    parser: JsonStreamParser,
+    /// error of the body or the parser, returned after the values matched before it
+    error: Option<ScraperError>,
+    finished: bool,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> JsonItemStream<T> {
+    /// constructor
+    pub fn new<S>(body: S, path: StreamJsonPath) -> Self
+    where
+        S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    {
+        Self {
+            body: Box::pin(body),
+            parser: JsonStreamParser::new(path),
+            error: None,
+            finished: false,
+            item: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Stream for JsonItemStream<T> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(json) = this.parser.next_match() {
+                return Poll::Ready(Some(serde_json::from_slice(&json).map_err(Into::into)));
+            }
+            if let Some(err) = this.error.take() {
+                return Poll::Ready(Some(Err(err)));
+            }
+            if this.finished {
+                return Poll::Ready(None);
+            }
+            let result = match this.body.as_mut().poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(chunk))) => this.parser.feed(&chunk),
+                Poll::Ready(Some(Err(err))) => Err(err.into()),
+                Poll::Ready(None) => {
+                    this.finished = true;
+                    this.parser.finish()
+                }
+            };
+            if let Err(err) = result {
+                this.finished = true;
+                this.error = Some(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_json_stream_parser() {
+        use super::*;
+
+        let json = "\u{FEFF}{
+            \"meta\": {\"total\": 3, \"data\": [\"not matched\"]},
+            \"data\": [
+                {\"id\": 1, \"name\": \"Tom \\\"]}\", \"tags\": [\"a\", {\"b\": null}]},
+                {\"id\": 2, \"name\": \"Caf\u{e9}\", \"score\": -1.5e3},
+                3,
+                true,
+                []
+            ],
+            \"da\\u0074a\": [\"escaped name\"]
+        }";
+
+        // feed the document in every chunk size to exercise the chunk boundaries
+        for chunk_size in [1, 2, 3, 7, 64, json.len()] {
+            let parse = |path: &str| {
+                let mut parser = JsonStreamParser::new(StreamJsonPath::parse(path).unwrap());
+                let mut matches = Vec::new();
+                for chunk in json.as_bytes().chunks(chunk_size) {
+                    parser.feed(chunk).unwrap();
+                    matches.extend(std::iter::from_fn(|| parser.next_match()));
+                }
+                parser.finish().unwrap();
+                matches.extend(std::iter::from_fn(|| parser.next_match()));
+                matches
+                    .into_iter()
+                    .map(|m| serde_json::from_slice::<serde_json::Value>(&m).unwrap())
+                    .collect::<Vec<_>>()
+            };
+
+            let items = parse("$.data[*]");
+            assert_eq!(items.len(), 6);
+            assert_eq!(items[0]["name"], "Tom \"]}");
+            assert_eq!(items[0]["tags"][1], serde_json::json!({"b": null}));
+            assert_eq!(items[1]["name"], "Café");
+            assert_eq!(items[1]["score"], -1500.0);
+            assert_eq!(items[2], 3);
+            assert_eq!(items[3], true);
+            assert_eq!(items[4], serde_json::json!([]));
+            assert_eq!(items[5], "escaped name");
+
+            assert_eq!(parse("$['data'][1].id"), [2]);
+            assert_eq!(parse("$.*.total"), [3]);
+            assert_eq!(parse("$.data[*].tags[*].b"), [serde_json::Value::Null]);
+            assert_eq!(parse("$.meta").len(), 1);
+            assert_eq!(parse("$").len(), 1);
+            assert!(parse("$.missing[*]").is_empty());
+        }
+    }
+
+    #[test]
+    fn test_invalid_json_stream() {
+        use super::*;
+
+        let parse = |json: &str| {
+            let mut parser = JsonStreamParser::new(StreamJsonPath::parse("$[*]").unwrap());
+            parser.feed(json.as_bytes())?;
+            parser.finish()
+        };
+        assert!(parse("[1, 2]").is_ok());
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("[1 2]").is_err());
+        assert!(parse("{\"a\" 1}").is_err());
+        assert!(parse("[1] [2]").is_err());
+        assert!(parse("").is_err());
+
+        // tokens outside the matched values are validated as well
+        let parse = |json: &[u8]| {
+            let mut parser = JsonStreamParser::new(StreamJsonPath::parse("$.x").unwrap());
+            parser.feed(json)?;
+            parser.finish()
+        };
+        assert!(parse(b"{\"y\": [true, null, -1.5e3, \"a\\n\"], \"x\": 1}").is_ok());
+        assert!(parse(b"[tru3]").is_err());
+        assert!(parse(b"[nulll]").is_err());
+        assert!(parse(b"[1.2.3]").is_err());
+        assert!(parse(b"{\"y\": [\"\\q\"]}").is_err());
+        assert!(parse(b"{\"y\": [\"\xFF\"]}").is_err());
+        assert!(parse(b"{\"y\": {\"\t\": 1}}").is_err());
+
+        assert!(StreamJsonPath::parse("$.data[*]['a b'][\"c\"][0]").is_ok());
+        assert!(StreamJsonPath::parse("$..data").is_err());
+        assert!(StreamJsonPath::parse("$.data[?(@.id)]").is_err());
+        assert!(StreamJsonPath::parse("$.data[-1]").is_err());
+        assert!(StreamJsonPath::parse("data").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_json_item_stream() {
+        use super::*;
+        use futures_util::StreamExt;
+
+        let chunks = [
+            "{\"data\": [{\"id\"",
+            ": 1}, {\"id\": 2}, {\"id\": \"x\"}",
+            "]}",
+        ]
+        .map(|chunk| Ok(Bytes::from(chunk)));
+        let mut items = JsonItemStream::<serde_json::Map<String, serde_json::Value>>::new(
+            futures_util::stream::iter(chunks),
+            StreamJsonPath::parse("$.data[*]").unwrap(),
+        );
+        assert_eq!(items.next().await.unwrap().unwrap()["id"], 1);
+        assert_eq!(items.next().await.unwrap().unwrap()["id"], 2);
+        assert_eq!(items.next().await.unwrap().unwrap()["id"], "x");
+        assert!(items.next().await.is_none());
+
+        let chunks = ["[1, 2, ", "\"x\", 3"].map(|chunk| Ok(Bytes::from(chunk)));
+        let mut items = JsonItemStream::<i64>::new(
+            futures_util::stream::iter(chunks),
+            StreamJsonPath::parse("$[*]").unwrap(),
+        );
+        assert_eq!(items.next().await.unwrap().unwrap(), 1);
+        assert_eq!(items.next().await.unwrap().unwrap(), 2);
+        // a value of another type fails alone, a truncated document ends the stream
+        assert!(items.next().await.unwrap().is_err());
+        assert_eq!(items.next().await.unwrap().unwrap(), 3);
+        assert!(items.next().await.unwrap().is_err());
+        assert!(items.next().await.is_none());
+    }
+}
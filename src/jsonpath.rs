@@ -1,77 +1,561 @@
 //!  Use JsonPath to select fields in json response
 //!
-use crate::error::{Result, ScraperError};
-use jsonpath_lib as jsonpath;
+use crate::{
+    error::{Result, ScraperError},
+    FromJsonPath,
+};
+use itertools::Itertools;
+use jsonpath_lib::{self as jsonpath, JsonPathError};
 use serde::de::DeserializeOwned;
+pub use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
 /// Json Response
 #[derive(Debug)]
 pub struct Json {
     value: serde_json::Value,
+    backend: JsonPathBackend,
 }
 
 impl Json {
     /// constructor
     pub fn new(json: &str) -> Result<Self> {
         let value = serde_json::from_str(json)?;
-        Ok(Self { value })
+        Ok(Self::from_value(value))
+    }
+
+    /// Wrap the parsed json value
+    pub fn from_value(value: Value) -> Self {
+        Self {
+            value,
+            backend: JsonPathBackend::default(),
+        }
+    }
+
+    /// Compile jsonpath strings passed to the `select` methods with the given backend
+    /// ```
+    /// # #[cfg(feature = "jsonpath_rfc9535")] {
+    /// use reqwest_scraper::jsonpath::{Json, JsonPathBackend};
+    ///
+    /// let json = Json::new(r#"{"items":[{"price":8},{"price":12}]}"#)
+    ///     .unwrap()
+    ///     .with_backend(JsonPathBackend::Rfc9535);
+    /// assert_eq!(json.select::<i64, _>("$.items[?@.price > 10].price").unwrap(), [12]);
+    /// # }
+    /// ```
+    pub fn with_backend(mut self, backend: JsonPathBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Returns the backend compiling jsonpath strings
+    pub fn backend(&self) -> JsonPathBackend {
+        self.backend
+    }
+
+    /// Returns the parsed json value
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Returns the json value, e.g. to archive it after [`Json::delete`] or [`Json::replace_with`]
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+
+    /// Extract the derived struct from the json
+    pub fn extract<T: FromJsonPath>(&self) -> T::JsonPathExtractResult {
+        T::from_value_with(&self.value, self.backend)
     }
 
     /// Use jsonpath to select json fragments and convert them into structures
-    pub fn select<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>> {
-        jsonpath::Selector::new()
-            .str_path(path)?
-            .value(&self.value)
-            .select_as()
-            .map_err(ScraperError::from)
+    pub fn select<T: DeserializeOwned, P: IntoJsonPath>(&self, path: P) -> Result<Vec<T>> {
+        path.into_jsonpath(self.backend)?
+            .select(&self.value)?
+            .into_iter()
+            .map(|v| T::deserialize(v).map_err(|e| JsonPathError::Serde(e.to_string()).into()))
+            .collect()
     }
 
     /// Use jsonpath to select json string fields
-    pub fn select_one<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
-        let result = jsonpath::Selector::new()
-            .str_path(path)?
-            .value(&self.value)
-            .select()?;
-        let v = result
-            .first()
-            .ok_or_else(|| {
-                ScraperError::JsonPathMatchError(format!(
-                    "The \"{}\" jsonpath did not find data in json",
-                    path
-                ))
-            })?
-            .to_owned();
-        Ok(serde_json::from_value::<T>(v.to_owned())?)
+    pub fn select_one<T: DeserializeOwned, P: IntoJsonPath>(&self, path: P) -> Result<T> {
+        let (_, v) = self.first(path)?;
+        Ok(T::deserialize(v)?)
     }
 
     /// Use jsonpath to select json fields as string
-    pub fn select_as_str(&self, path: &str) -> Result<String> {
-        jsonpath::Selector::new()
-            .str_path(path)?
-            .value(&self.value)
-            .select_as_str()
-            .map_err(ScraperError::from)
-    }
-
-    /// Use jsonpath to select one json fields as string
-    pub fn select_one_as_str(&self, path: &str) -> Result<String> {
-        let result = jsonpath::Selector::new()
-            .str_path(path)?
-            .value(&self.value)
-            .select()?;
-        let v = result
-            .first()
-            .ok_or_else(|| {
-                ScraperError::JsonPathMatchError(format!(
-                    "The \"{}\" jsonpath did not find data in json",
-                    path
-                ))
-            })?
-            .to_owned();
-        Ok(v.to_string())
+    pub fn select_as_str<P: IntoJsonPath>(&self, path: P) -> Result<String> {
+        let result = path.into_jsonpath(self.backend)?.select(&self.value)?;
+        Ok(serde_json::to_string(&result)?)
+    }
+
+    /// Use jsonpath to select one scalar json field as text,
+    /// strings are returned without quotes, numbers and booleans are formatted
+    /// ```
+    /// use reqwest_scraper::jsonpath::Json;
+    ///
+    /// let json = Json::new(r#"{"name":"Tom","age":18}"#).unwrap();
+    /// assert_eq!(json.select_one_as_str("$.name").unwrap(), "Tom");
+    /// assert_eq!(json.select_one_as_str("$.age").unwrap(), "18");
+    /// ```
+    pub fn select_one_as_str<P: IntoJsonPath>(&self, path: P) -> Result<String> {
+        let (path, v) = self.first(path)?;
+        match v {
+            Value::String(s) => Ok(s.clone()),
+            Value::Number(n) => Ok(n.to_string()),
+            Value::Bool(b) => Ok(b.to_string()),
+            _ => Err(type_mismatch(&path, "string, number or boolean", v)),
+        }
+    }
+
+    /// Use jsonpath to select one json integer
+    pub fn select_i64<P: IntoJsonPath>(&self, path: P) -> Result<i64> {
+        let (path, v) = self.first(path)?;
+        v.as_i64().ok_or_else(|| type_mismatch(&path, "integer", v))
+    }
+
+    /// Use jsonpath to select one json number
+    pub fn select_f64<P: IntoJsonPath>(&self, path: P) -> Result<f64> {
+        let (path, v) = self.first(path)?;
+        v.as_f64().ok_or_else(|| type_mismatch(&path, "number", v))
+    }
+
+    /// Use jsonpath to select one json boolean
+    pub fn select_bool<P: IntoJsonPath>(&self, path: P) -> Result<bool> {
+        let (path, v) = self.first(path)?;
+        v.as_bool()
+            .ok_or_else(|| type_mismatch(&path, "boolean", v))
+    }
+
+    /// Whether the first value matched by the jsonpath is `null`,
+    /// a missing value is an error rather than `null`
+    pub fn is_null<P: IntoJsonPath>(&self, path: P) -> Result<bool> {
+        Ok(self.first(path)?.1.is_null())
+    }
+
+    /// The first value matched by the jsonpath
+    fn first<P: IntoJsonPath>(&self, path: P) -> Result<(JsonPath, &Value)> {
+        let path = path.into_jsonpath(self.backend)?;
+        let first = path.select(&self.value)?.into_iter().next();
+        match first {
+            Some(v) => Ok((path, v)),
+            None => Err(ScraperError::JsonPathNotFound {
+                path: path.as_str().into(),
+            }),
+        }
+    }
+
+    /// Use jsonpath to select json fragments with their normalized paths,
+    /// e.g. `$['data'][0]['email']`, to know where each value came from
+    pub fn select_with_paths<P: IntoJsonPath>(&self, path: P) -> Result<Vec<(String, &Value)>> {
+        let result = path.into_jsonpath(self.backend)?.select(&self.value)?;
+        let paths = locate(&self.value, &result);
+        Ok(result
+            .into_iter()
+            .filter_map(|v| Some((normalized_path(paths.get(&(v as *const Value))?), v)))
+            .collect())
+    }
+
+    /// Delete the values matched by the jsonpath,
+    /// object members and array elements are removed. Returns the number of deleted values
+    pub fn delete<P: IntoJsonPath>(&mut self, path: P) -> Result<usize> {
+        self.replace_with(path, |_| None)
+    }
+
+    /// Replace the values matched by the jsonpath with the result of `f`,
+    /// the value is removed if `f` returns `None`. Returns the number of matched values
+    /// ```
+    /// use reqwest_scraper::jsonpath::{Json, Value};
+    ///
+    /// let mut json = Json::new(r#"{"users":[{"name":"Tom","email":"tom@example.com"}]}"#).unwrap();
+    /// json.replace_with("$..email", |_| Some(Value::from("***"))).unwrap();
+    /// assert_eq!(json.value()["users"][0]["email"], "***");
+    /// ```
+    pub fn replace_with<P, F>(&mut self, path: P, mut f: F) -> Result<usize>
+    where
+        P: IntoJsonPath,
+        F: FnMut(Value) -> Option<Value>,
+    {
+        let mut paths = {
+            let result = path.into_jsonpath(self.backend)?.select(&self.value)?;
+            let paths = locate(&self.value, &result);
+            result
+                .into_iter()
+                .filter_map(|v| paths.get(&(v as *const Value)).cloned())
+                .unique()
+                .collect_vec()
+        };
+        // the members of a matched value, and the array elements after it, are replaced first,
+        // so the remaining paths still point to their values
+        paths.sort_unstable_by(|a, b| b.cmp(a));
+        let count = paths.len();
+        for path in paths {
+            replace_value(&mut self.value, &path, &mut f);
+        }
+        Ok(count)
+    }
+}
+
+/// Compiled jsonpath, reusable across documents.
+///
+/// Compile it once, e.g. in a `static`, and pass it to `select` to avoid re-parsing the path:
+/// ```
+/// use reqwest_scraper::jsonpath::{Json, JsonPath};
+/// use std::sync::LazyLock;
+///
+/// static NAMES: LazyLock<JsonPath> = LazyLock::new(|| JsonPath::compile("$.items[*].name").unwrap());
+///
+/// let json = Json::new(r#"{"items":[{"name":"a"},{"name":"b"}]}"#).unwrap();
+/// assert_eq!(json.select::<String, _>(&*NAMES).unwrap(), ["a", "b"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    engine: Engine,
+    path: Arc<str>,
+}
+
+/// Compiled expression of a [`JsonPathBackend`]
+#[derive(Debug, Clone)]
+enum Engine {
+    JsonPathLib(Arc<jsonpath::Compiled>),
+    #[cfg(feature = "jsonpath_rfc9535")]
+    Rfc9535(Arc<serde_json_path::JsonPath>),
+}
+
+/// Engine that compiles and evaluates jsonpath expressions.
+///
+/// The dialects differ on filters, slices and the `..` descendant operator,
+/// pick one with [`Json::with_backend`] or [`JsonPath::compile_with`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum JsonPathBackend {
+    /// [jsonpath_lib](https://docs.rs/jsonpath_lib) dialect
+    #[default]
+    JsonPathLib,
+    /// [RFC 9535](https://www.rfc-editor.org/rfc/rfc9535) compliant engine,
+    /// provided by [serde_json_path](https://docs.rs/serde_json_path)
+    #[cfg(feature = "jsonpath_rfc9535")]
+    Rfc9535,
+}
+
+impl JsonPath {
+    /// Compile the jsonpath with the default backend
+    pub fn compile(path: &str) -> Result<Self> {
+        Self::compile_with(path, JsonPathBackend::default())
+    }
+
+    /// Compile the jsonpath with the given backend
+    pub fn compile_with(path: &str, backend: JsonPathBackend) -> Result<Self> {
+        let engine = match backend {
+            JsonPathBackend::JsonPathLib => Engine::JsonPathLib(Arc::new(
+                jsonpath::Compiled::compile(path).map_err(JsonPathError::Path)?,
+            )),
+            #[cfg(feature = "jsonpath_rfc9535")]
+            JsonPathBackend::Rfc9535 => Engine::Rfc9535(Arc::new(
+                serde_json_path::JsonPath::parse(path)
+                    .map_err(|e| JsonPathError::Path(e.to_string()))?,
+            )),
+        };
+        Ok(Self {
+            engine,
+            path: path.into(),
+        })
+    }
+
+    /// Returns the jsonpath
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the backend that compiled the jsonpath
+    pub fn backend(&self) -> JsonPathBackend {
+        match self.engine {
+            Engine::JsonPathLib(_) => JsonPathBackend::JsonPathLib,
+            #[cfg(feature = "jsonpath_rfc9535")]
+            Engine::Rfc9535(_) => JsonPathBackend::Rfc9535,
+        }
+    }
+
+    /// Select the values matched by the jsonpath
+    pub fn select<'a>(&self, value: &'a Value) -> Result<Vec<&'a Value>> {
+        match &self.engine {
+            Engine::JsonPathLib(compiled) => Ok(compiled.select(value)?),
+            #[cfg(feature = "jsonpath_rfc9535")]
+            Engine::Rfc9535(path) => Ok(path.query(value).all()),
+        }
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = ScraperError;
+
+    fn from_str(path: &str) -> Result<Self> {
+        Self::compile(path)
+    }
+}
+
+/// Jsonpath string or compiled [`JsonPath`] accepted by the `select` methods of [`Json`]
+pub trait IntoJsonPath {
+    /// Returns the compiled jsonpath, strings are compiled with the `backend` of the [`Json`]
+    fn into_jsonpath(self, backend: JsonPathBackend) -> Result<JsonPath>;
+}
+
+impl IntoJsonPath for &str {
+    fn into_jsonpath(self, backend: JsonPathBackend) -> Result<JsonPath> {
+        JsonPath::compile_with(self, backend)
+    }
+}
+
+impl IntoJsonPath for &String {
+    fn into_jsonpath(self, backend: JsonPathBackend) -> Result<JsonPath> {
+        JsonPath::compile_with(self, backend)
+    }
+}
+
+impl IntoJsonPath for JsonPath {
+    fn into_jsonpath(self, _backend: JsonPathBackend) -> Result<JsonPath> {
+        Ok(self)
+    }
+}
+
+impl IntoJsonPath for &JsonPath {
+    fn into_jsonpath(self, _backend: JsonPathBackend) -> Result<JsonPath> {
+        Ok(self.clone())
+    }
+}
+
+/// Error for a value of another json type than `expected`
+fn type_mismatch(path: &JsonPath, expected: &'static str, value: &Value) -> ScraperError {
+    let found = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    };
+    ScraperError::JsonPathTypeMismatch {
+        path: path.as_str().into(),
+        expected,
+        found,
+    }
+}
+
+/// Step from a json value to one of its members or elements
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum PathSegment {
+    Index(usize),
+    Key(String),
+}
+
+/// Find the paths of the matched values in the document
+fn locate(root: &Value, matches: &[&Value]) -> HashMap<*const Value, Vec<PathSegment>> {
+    fn walk(
+        value: &Value,
+        path: &mut Vec<PathSegment>,
+        targets: &HashSet<*const Value>,
+        found: &mut HashMap<*const Value, Vec<PathSegment>>,
+    ) {
+        if found.len() == targets.len() {
+            return;
+        }
+        if targets.contains(&(value as *const Value)) {
+            found.insert(value, path.clone());
+        }
+        match value {
+            Value::Array(array) => {
+                for (i, v) in array.iter().enumerate() {
+                    path.push(PathSegment::Index(i));
+                    walk(v, path, targets, found);
+                    path.pop();
+                }
+            }
+            Value::Object(map) => {
+                for (k, v) in map {
+                    path.push(PathSegment::Key(k.clone()));
+                    walk(v, path, targets, found);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let targets = matches.iter().map(|v| *v as *const Value).collect();
+    let mut found = HashMap::new();
+    walk(root, &mut Vec::new(), &targets, &mut found);
+    found
+}
+
+/// Format the path like a RFC 9535 normalized path, e.g. `$['data'][0]`
+fn normalized_path(path: &[PathSegment]) -> String {
+    let mut normalized = String::from("$");
+    for segment in path {
+        match segment {
+            PathSegment::Index(i) => normalized.push_str(&format!("[{}]", i)),
+            PathSegment::Key(key) => {
+                normalized.push_str("['");
+                for c in key.chars() {
+                    match c {
+                        '\'' => normalized.push_str("\\'"),
+                        '\\' => normalized.push_str("\\\\"),
+                        '\u{8}' => normalized.push_str("\\b"),
+                        '\u{c}' => normalized.push_str("\\f"),
+                        '\n' => normalized.push_str("\\n"),
+                        '\r' => normalized.push_str("\\r"),
+                        '\t' => normalized.push_str("\\t"),
+                        c if c < ' ' => normalized.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => normalized.push(c),
+                    }
+                }
+                normalized.push_str("']");
+            }
+        }
+    }
+    normalized
+}
+
+fn replace_value<F>(root: &mut Value, path: &[PathSegment], f: &mut F)
+where
+    F: FnMut(Value) -> Option<Value>,
+{
+    let Some((last, parents)) = path.split_last() else {
+        let value = std::mem::take(root);
+        *root = f(value).unwrap_or_default();
+        return;
+    };
+    let mut target = root;
+    for segment in parents {
+        target = match (target, segment) {
+            (Value::Array(array), PathSegment::Index(i)) => match array.get_mut(*i) {
+                Some(v) => v,
+                None => return,
+            },
+            (Value::Object(map), PathSegment::Key(k)) => match map.get_mut(k) {
+                Some(v) => v,
+                None => return,
+            },
+            _ => return,
+        };
+    }
+    match (target, last) {
+        (Value::Array(array), PathSegment::Index(i)) if *i < array.len() => {
+            match f(std::mem::take(&mut array[*i])) {
+                Some(value) => array[*i] = value,
+                None => {
+                    array.remove(*i);
+                }
+            }
+        }
+        (Value::Object(map), PathSegment::Key(k)) => {
+            if let Some(value) = map.get_mut(k) {
+                match f(std::mem::take(value)) {
+                    Some(replaced) => *value = replaced,
+                    None => {
+                        map.remove(k);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Select the values matched by the jsonpath, relative to `value`,
+/// a jsonpath string is compiled with the default backend.
+#[doc(hidden)]
+pub fn select_values<P: IntoJsonPath>(value: &Value, path: P) -> Result<Vec<&Value>> {
+    select_values_with(value, path, JsonPathBackend::default())
+}
+
+/// Select the values matched by the jsonpath, relative to `value`,
+/// a jsonpath string is compiled with the given backend.
+///
+/// Used by the code generated from `#[derive(FromJsonPath)]`.
+#[doc(hidden)]
+pub fn select_values_with<P: IntoJsonPath>(
+    value: &Value,
+    path: P,
+    backend: JsonPathBackend,
+) -> Result<Vec<&Value>> {
+    path.into_jsonpath(backend)?.select(value)
+}
+
+/// Jsonpath of a `FromJsonPath` field, compiled once per backend on first use.
+///
+/// Used by the code generated from `#[derive(FromJsonPath)]`.
+#[doc(hidden)]
+pub struct JsonPathCache {
+    path: &'static str,
+    jsonpath_lib: OnceLock<Option<JsonPath>>,
+    #[cfg(feature = "jsonpath_rfc9535")]
+    rfc9535: OnceLock<Option<JsonPath>>,
+}
+
+impl JsonPathCache {
+    /// Cache of the jsonpath, nothing is compiled yet
+    pub const fn new(path: &'static str) -> Self {
+        Self {
+            path,
+            jsonpath_lib: OnceLock::new(),
+            #[cfg(feature = "jsonpath_rfc9535")]
+            rfc9535: OnceLock::new(),
+        }
+    }
+}
+
+impl IntoJsonPath for &JsonPathCache {
+    /// an invalid path is compiled again to return its error
+    fn into_jsonpath(self, backend: JsonPathBackend) -> Result<JsonPath> {
+        let compiled = match backend {
+            JsonPathBackend::JsonPathLib => &self.jsonpath_lib,
+            #[cfg(feature = "jsonpath_rfc9535")]
+            JsonPathBackend::Rfc9535 => &self.rfc9535,
+        };
+        match compiled.get_or_init(|| JsonPath::compile_with(self.path, backend).ok()) {
+            Some(path) => Ok(path.clone()),
+            None => JsonPath::compile_with(self.path, backend),
+        }
     }
 }
 
+/// Deserialize the value matched for a field of a `FromJsonPath` struct.
+///
+/// Used by the code generated from `#[derive(FromJsonPath)]`.
+#[doc(hidden)]
+pub fn parse_field<T: DeserializeOwned>(
+    struct_name: &'static str,
+    field: &'static str,
+    path: &'static str,
+    value: &Value,
+) -> Result<T> {
+    T::deserialize(value).map_err(|err| ScraperError::JsonPathParseError {
+        struct_name,
+        field,
+        path,
+        value: value.to_string(),
+        message: err.to_string(),
+    })
+}
+
+/// Deserialize the default value of a `FromJsonPath` field whose jsonpath matched nothing,
+/// the default is JSON text, e.g. `0` or `[]`, otherwise it is used as a string.
+///
+/// Used by the code generated from `#[derive(FromJsonPath)]`.
+#[doc(hidden)]
+pub fn parse_default<T: DeserializeOwned>(
+    struct_name: &'static str,
+    field: &'static str,
+    path: &'static str,
+    default: &'static str,
+) -> Result<T> {
+    serde_json::from_str(default)
+        .or_else(|_| parse_field(struct_name, field, path, &Value::String(default.into())))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -94,7 +578,7 @@ mod tests {
 
         assert_eq!(
             json.select_one_as_str("$.time").unwrap(),
-            r#""2020.10.12 21:22:34""#
+            "2020.10.12 21:22:34"
         );
 
         #[derive(Deserialize)]
@@ -118,4 +602,241 @@ mod tests {
         assert_eq!(d2.b, "b2");
         assert_eq!(d2.c, 2);
     }
+
+    #[test]
+    fn test_parse_field() {
+        use super::*;
+
+        let value = serde_json::json!({"id": "x", "count": 2});
+        let count: u32 = parse_field("Item", "count", "$.count", &value["count"]).unwrap();
+        assert_eq!(count, 2);
+        let error = parse_field::<u32>("Item", "id", "$.id", &value["id"]).unwrap_err();
+        assert!(matches!(
+            error,
+            ScraperError::JsonPathParseError { field: "id", ref value, .. } if value == "\"x\""
+        ));
+
+        assert_eq!(
+            parse_default::<u32>("Item", "count", "$.count", "0").unwrap(),
+            0
+        );
+        assert_eq!(
+            parse_default::<Vec<String>>("Item", "tags", "$.tags[*]", "[]").unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            parse_default::<String>("Item", "name", "$.name", "<unname>").unwrap(),
+            "<unname>"
+        );
+        assert!(parse_default::<u32>("Item", "count", "$.count", "none").is_err());
+    }
+
+    #[test]
+    fn test_compiled_jsonpath() {
+        use super::*;
+
+        let names = JsonPath::compile("$.items[*].name").unwrap();
+        let first = Json::new(r#"{"items":[{"name":"a"},{"name":"b"}]}"#).unwrap();
+        let second = Json::new(r#"{"items":[{"name":"c"}]}"#).unwrap();
+        assert_eq!(first.select::<String, _>(&names).unwrap(), ["a", "b"]);
+        assert_eq!(second.select::<String, _>(&names).unwrap(), ["c"]);
+        assert_eq!(second.select_one::<String, _>(names).unwrap(), "c");
+
+        let filter = JsonPath::compile("$.items[?(@.name == 'b')]").unwrap();
+        assert_eq!(
+            first.select_with_paths(&filter).unwrap()[0].0,
+            "$['items'][1]"
+        );
+
+        assert!(JsonPath::compile("$.[[").is_err());
+        assert!("$..name".parse::<JsonPath>().is_ok());
+    }
+
+    #[test]
+    fn test_paths_and_mutation() {
+        use super::*;
+
+        let mut json = Json::new(
+            r#"{
+                "users": [
+                    {"name": "Tom", "email": "tom@example.com", "tags": [1, 2, 3, 4]},
+                    {"name": "Ann", "email": "ann@example.com", "it's": true}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let emails = json.select_with_paths("$..email").unwrap();
+        assert_eq!(
+            emails,
+            [
+                (
+                    "$['users'][0]['email']".to_string(),
+                    &Value::from("tom@example.com")
+                ),
+                (
+                    "$['users'][1]['email']".to_string(),
+                    &Value::from("ann@example.com")
+                ),
+            ]
+        );
+        let paths = json.select_with_paths("$.users[1].*").unwrap();
+        assert!(paths
+            .iter()
+            .any(|(path, _)| path == r"$['users'][1]['it\'s']"));
+
+        assert_eq!(
+            json.replace_with("$..email", |_| Some(Value::from("***")))
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            json.select::<String, _>("$..email").unwrap(),
+            ["***", "***"]
+        );
+
+        // array elements are removed from the last one, so the indexes stay valid
+        assert_eq!(json.delete("$.users[0].tags[1,3]").unwrap(), 2);
+        assert_eq!(json.value()["users"][0]["tags"], serde_json::json!([1, 3]));
+        assert_eq!(json.delete("$.users[*].name").unwrap(), 2);
+        assert!(json.select::<String, _>("$..name").unwrap().is_empty());
+        assert_eq!(json.delete("$.missing").unwrap(), 0);
+
+        assert_eq!(
+            json.into_value(),
+            serde_json::json!({"users": [{"email": "***", "tags": [1, 3]}, {"email": "***", "it's": true}]})
+        );
+    }
+
+    #[test]
+    fn test_typed_select() {
+        use super::*;
+
+        let json = Json::new(
+            r#"{"name": "Tom", "age": 18, "score": 9.5, "vip": true, "email": null, "tags": ["a"]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(json.select_one_as_str("$.name").unwrap(), "Tom");
+        assert_eq!(json.select_one_as_str("$.age").unwrap(), "18");
+        assert_eq!(json.select_one_as_str("$.vip").unwrap(), "true");
+        assert_eq!(json.select_i64("$.age").unwrap(), 18);
+        assert_eq!(json.select_f64("$.score").unwrap(), 9.5);
+        assert_eq!(json.select_f64("$.age").unwrap(), 18.0);
+        assert!(json.select_bool("$.vip").unwrap());
+        assert!(json.is_null("$.email").unwrap());
+        assert!(!json.is_null("$.name").unwrap());
+
+        match json.select_i64("$.missing") {
+            Err(ScraperError::JsonPathNotFound { path }) => assert_eq!(path, "$.missing"),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert!(matches!(
+            json.select_one::<String, _>("$.missing"),
+            Err(ScraperError::JsonPathNotFound { ref path }) if path == "$.missing"
+        ));
+        assert!(matches!(
+            json.is_null("$.missing"),
+            Err(ScraperError::JsonPathNotFound { .. })
+        ));
+        match json.select_i64("$.score") {
+            Err(ScraperError::JsonPathTypeMismatch {
+                path,
+                expected,
+                found,
+            }) => {
+                assert_eq!(path, "$.score");
+                assert_eq!(expected, "integer");
+                assert_eq!(found, "number");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert!(matches!(
+            json.select_one_as_str("$.tags"),
+            Err(ScraperError::JsonPathTypeMismatch { found: "array", .. })
+        ));
+        assert!(matches!(
+            json.select_bool("$.email"),
+            Err(ScraperError::JsonPathTypeMismatch { found: "null", .. })
+        ));
+    }
+
+    #[test]
+    fn test_jsonpath_backend() {
+        use super::*;
+
+        let json = Json::new(r#"{"a": [1, 2, 3]}"#).unwrap();
+        assert_eq!(json.backend(), JsonPathBackend::JsonPathLib);
+        let path = JsonPath::compile("$.a[1]").unwrap();
+        assert_eq!(path.backend(), JsonPathBackend::JsonPathLib);
+        assert_eq!(json.select::<i64, _>(&path).unwrap(), [2]);
+
+        #[cfg(feature = "jsonpath_rfc9535")]
+        {
+            let json = json.with_backend(JsonPathBackend::Rfc9535);
+            assert_eq!(json.select::<i64, _>("$.a[?@ > 1]").unwrap(), [2, 3]);
+            // a compiled jsonpath keeps its own backend
+            assert_eq!(json.select::<i64, _>(&path).unwrap(), [2]);
+            assert!(JsonPath::compile_with("$[01]", JsonPathBackend::Rfc9535).is_err());
+        }
+    }
+
+    /// Runs the JSONPath Compliance Test Suite against the RFC 9535 backend
+    #[test]
+    #[cfg(feature = "jsonpath_rfc9535")]
+    fn test_rfc9535_compliance() {
+        use super::*;
+
+        let cases: Value =
+            serde_json::from_str(include_str!("../tests/jsonpath-rfc9535/cts.json")).unwrap();
+        let failures = cases["tests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|case| {
+                let name = case["name"].as_str().unwrap();
+                let selector = case["selector"].as_str().unwrap();
+                let path = JsonPath::compile_with(selector, JsonPathBackend::Rfc9535);
+                if case["invalid_selector"] == true {
+                    return path
+                        .is_ok()
+                        .then(|| format!("{name}: {selector:?} should be invalid"));
+                }
+                let path = match path {
+                    Ok(path) => path,
+                    Err(e) => return Some(format!("{name}: {selector:?} {e}")),
+                };
+                let json = Json::from_value(case["document"].clone());
+                let actual = Value::Array(json.select(&path).unwrap());
+                // every match is located in the document, so delete and replace work on it
+                let paths = json
+                    .select_with_paths(&path)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(path, _)| Value::from(path))
+                    .collect_vec();
+                let paths = Value::Array(paths);
+                let passed = match case.get("result") {
+                    Some(expected) => {
+                        &actual == expected
+                            && case.get("result_paths").into_iter().all(|e| e == &paths)
+                    }
+                    // the members of an object are selected in any order
+                    None => {
+                        let results_paths = &case["results_paths"];
+                        case["results"].as_array().unwrap().iter().enumerate().any(
+                            |(i, expected)| {
+                                &actual == expected
+                                    && (results_paths.is_null() || results_paths[i] == paths)
+                            },
+                        )
+                    }
+                };
+                (!passed).then(|| format!("{name}: {selector:?} selected {actual} at {paths}"))
+            })
+            .collect_vec();
+        // no known failures of serde_json_path in the suite
+        let known_failures: [&str; 0] = [];
+        assert_eq!(failures, known_failures, "\n{}", failures.join("\n"));
+    }
 }
@@ -3,11 +3,19 @@
 //! reqwest-scraper
 //#![doc = include_str!("../README.md")]
 
+pub mod charset;
 #[cfg(feature = "css_selector")]
 pub mod css_selector;
 pub mod error;
+#[cfg(feature = "html_stream")]
+pub mod html_stream;
+#[cfg(feature = "jmespath")]
+pub mod jmespath;
+#[cfg(feature = "json_stream")]
+pub mod json_stream;
 #[cfg(feature = "jsonpath")]
 pub mod jsonpath;
+pub mod status;
 #[cfg(feature = "xpath")]
 pub mod xpath;
 
@@ -15,21 +23,48 @@ pub mod xpath;
 use std::future::Future;
 
 #[cfg(feature = "css_selector")]
-use crate::css_selector::Html;
+use crate::css_selector::{Html, SelectItem};
 use crate::error::Result;
+#[cfg(feature = "html_stream")]
+use crate::html_stream::{HtmlElementStream, StreamSelector};
+#[cfg(feature = "jmespath")]
+use crate::jmespath::JmesJson;
+#[cfg(feature = "json_stream")]
+use crate::json_stream::{JsonItemStream, StreamJsonPath};
 #[cfg(feature = "jsonpath")]
-use crate::jsonpath::Json;
+use crate::jsonpath::{Json, JsonPathBackend};
 #[cfg(feature = "xpath")]
-use crate::xpath::XHtml;
-use encoding_rs::{Encoding, UTF_8};
-use error::ScraperError;
-use mime::Mime;
-use regex::Regex;
+use crate::xpath::{Node, XHtml};
+use charset::detect_encoding;
 pub use reqwest::Response;
-#[cfg(feature = "json")]
+#[cfg(any(feature = "json", feature = "json_stream"))]
 use serde::de::DeserializeOwned;
+use status::StatusPolicy;
 
-pub use reqwest_scraper_macros::{include_http, FromCssSelector, FromXPath};
+pub use reqwest_scraper_macros::{include_http, FromCssSelector, FromJsonPath, FromXPath};
+
+/// Use JsonPath to extract the JSON response body into the derived struct
+#[cfg(feature = "jsonpath")]
+pub trait FromJsonPath {
+    /// extract result by jsonpath
+    type JsonPathExtractResult;
+
+    /// From Json Response, jsonpaths are compiled with the backend of the [`Json`]
+    fn from_json(json: Json) -> Self::JsonPathExtractResult {
+        Self::from_value_with(json.value(), json.backend())
+    }
+
+    /// From Json value, jsonpaths are evaluated relative to the value
+    fn from_value(value: &serde_json::Value) -> Self::JsonPathExtractResult {
+        Self::from_value_with(value, JsonPathBackend::default())
+    }
+
+    /// From Json value, jsonpaths are compiled with the given backend and evaluated relative to the value
+    fn from_value_with(
+        value: &serde_json::Value,
+        backend: JsonPathBackend,
+    ) -> Self::JsonPathExtractResult;
+}
 
 /// Use XPath to extract the HTML response body into the derived struct
 #[cfg(feature = "xpath")]
@@ -39,6 +74,9 @@ pub trait FromXPath {
 
     /// From Html Response
     fn from_xhtml(html: XHtml) -> Self::XPathExtractResult;
+
+    /// From Html Node, xpaths are evaluated relative to the node
+    fn from_node(node: &Node) -> Self::XPathExtractResult;
 }
 
 /// Use CssSelector to extract the HTML response body into the derived struct
@@ -49,20 +87,42 @@ pub trait FromCssSelector {
 
     /// From Html Response
     fn from_html(html: Html) -> Self::CssSelectorExtractResult;
+
+    /// From Html Element, selectors are evaluated relative to the element
+    fn from_select_item(item: &SelectItem) -> Self::CssSelectorExtractResult;
 }
 
 /// Support extended traits of jsonpath, css selector, and xpath
+///
+/// Only `2xx` responses are scraped by default, use [`ScraperResponse::with_status_policy`]
+/// to accept other status codes or to deserialize JSON error bodies.
 pub trait ScraperResponse {
+    /// Check the response status with the policy instead of only accepting `2xx`
+    fn with_status_policy(self, policy: StatusPolicy) -> PolicyResponse;
+
     /// Use jsonpath to select the response body
     #[cfg(feature = "jsonpath")]
     fn jsonpath(self) -> impl Future<Output = Result<Json>>;
 
+    /// Use JMESPath to query the response body
+    #[cfg(feature = "jmespath")]
+    fn jmespath(self) -> impl Future<Output = Result<JmesJson>>;
+
+    /// Use a simple jsonpath, e.g. `$.data[*]`, to deserialize the matched values while the response body is streamed,
+    /// each value is emitted as soon as it is complete, so the full document is never held in memory.
+    /// See [`StreamJsonPath`] for the supported jsonpath syntax.
+    #[cfg(feature = "json_stream")]
+    fn jsonpath_stream<T: DeserializeOwned>(
+        self,
+        path: &str,
+    ) -> impl Future<Output = Result<JsonItemStream<T>>>;
+
     /// works with any existing Serde Deserializer and exposes the chain of field names leading to the error.
     /// * https://crates.io/crates/serde_path_to_error
     #[cfg(feature = "json")]
     fn json_with_path_to_err<T: DeserializeOwned>(self) -> impl Future<Output = Result<T>>;
 
-    /// Use CSS selector to select the response body
+    /// Use CSS selector to select the response body, parsed as a complete HTML document
     #[cfg(feature = "css_selector")]
     fn css_selector(self) -> impl Future<Output = Result<Html>>;
 
@@ -70,173 +130,202 @@ pub trait ScraperResponse {
     #[cfg(feature = "xpath")]
     fn xpath(self) -> impl Future<Output = Result<XHtml>>;
 
-    /// If there is no Encoding method in the Content-Type of the response header,
-    /// try to read the meta information in the HTML to obtain the encoding.
-    /// eg: <meta charset="gb2312">
+    /// Parse the XML response, e.g. a sitemap, a RSS feed or a SOAP response, with the strict XML parser.
+    /// The result is queried with xpath like [`ScraperResponse::xpath`]
+    #[cfg(feature = "xpath")]
+    fn xml(self) -> impl Future<Output = Result<XHtml>>;
+
+    /// Use a restricted CSS selector to extract elements while the response body is streamed,
+    /// each matched element is emitted as soon as its end tag is reached.
+    /// See [`StreamSelector`] for the supported selector syntax.
+    #[cfg(feature = "html_stream")]
+    fn css_selector_stream(self, selector: &str)
+        -> impl Future<Output = Result<HtmlElementStream>>;
+
+    /// Decode the HTML with the encoding of the byte order mark or the Content-Type of the response header,
+    /// otherwise the encoding declared in the HTML is used.
+    /// eg: <meta charset="gb2312">, see [`charset::detect_encoding`]
     fn html(self) -> impl Future<Output = Result<String>>;
 }
 
 impl ScraperResponse for Response {
+    fn with_status_policy(self, policy: StatusPolicy) -> PolicyResponse {
+        PolicyResponse {
+            response: self,
+            policy,
+        }
+    }
+
     #[cfg(feature = "jsonpath")]
     async fn jsonpath(self) -> Result<Json> {
-        if self.status().is_success() {
-            let json = self.text().await?;
-            Ok(Json::new(json.as_str())?)
-        } else {
-            let url = self.url().to_string();
-            let status_code = self.status().as_u16();
-            let response = self.text().await?;
-            Err(ScraperError::HttpError(url, status_code, response))
-        }
+        self.with_status_policy(StatusPolicy::default())
+            .jsonpath()
+            .await
+    }
+
+    #[cfg(feature = "jmespath")]
+    async fn jmespath(self) -> Result<JmesJson> {
+        self.with_status_policy(StatusPolicy::default())
+            .jmespath()
+            .await
+    }
+
+    #[cfg(feature = "json_stream")]
+    async fn jsonpath_stream<T: DeserializeOwned>(self, path: &str) -> Result<JsonItemStream<T>> {
+        self.with_status_policy(StatusPolicy::default())
+            .jsonpath_stream(path)
+            .await
     }
 
     #[cfg(feature = "json")]
     async fn json_with_path_to_err<T: DeserializeOwned>(self) -> Result<T> {
-        let full = self.bytes().await?;
-        let mut deserializer = serde_json::Deserializer::from_slice(&full);
-        Ok(serde_path_to_error::deserialize(&mut deserializer)?)
+        self.with_status_policy(StatusPolicy::default())
+            .json_with_path_to_err()
+            .await
     }
 
     #[cfg(feature = "css_selector")]
     async fn css_selector(self) -> Result<Html> {
-        if self.status().is_success() {
-            let html_str = self.html().await?;
-            Ok(Html::new(html_str.as_str()))
-        } else {
-            let url = self.url().to_string();
-            let status_code = self.status().as_u16();
-            let response = self.text().await?;
-            Err(ScraperError::HttpError(url, status_code, response))
-        }
+        self.with_status_policy(StatusPolicy::default())
+            .css_selector()
+            .await
     }
 
     #[cfg(feature = "xpath")]
     async fn xpath(self) -> Result<XHtml> {
-        if self.status().is_success() {
-            let html_str = self.html().await?;
-            Ok(XHtml::new(html_str)?)
-        } else {
-            let url = self.url().to_string();
-            let status_code = self.status().as_u16();
-            let response = self.text().await?;
-            Err(ScraperError::HttpError(url, status_code, response))
-        }
+        self.with_status_policy(StatusPolicy::default())
+            .xpath()
+            .await
+    }
+
+    #[cfg(feature = "xpath")]
+    async fn xml(self) -> Result<XHtml> {
+        self.with_status_policy(StatusPolicy::default()).xml().await
+    }
+
+    #[cfg(feature = "html_stream")]
+    async fn css_selector_stream(self, selector: &str) -> Result<HtmlElementStream> {
+        self.with_status_policy(StatusPolicy::default())
+            .css_selector_stream(selector)
+            .await
     }
 
     async fn html(self) -> Result<String> {
-        let content_type = self
-            .headers()
-            .get(reqwest::header::CONTENT_TYPE)
-            .and_then(|value| value.to_str().ok())
-            .and_then(|value| value.parse::<Mime>().ok());
-        let encoding_name = content_type
-            .as_ref()
-            .and_then(|mime| mime.get_param("charset").map(|charset| charset.as_str()));
-
-        let full = self.bytes().await?;
-        match encoding_name {
-            Some(encoding_name) => {
-                let encoding = Encoding::for_label(encoding_name.as_bytes()).unwrap_or(UTF_8);
-                let (text, _, _) = encoding.decode(&full);
-                Ok(text.into_owned())
-            }
-            None => {
-                let (text, _, _) = UTF_8.decode(&full);
-                let meta_charset = extract_charset(&text);
-                if let Some(meta_charset) = meta_charset {
-                    let encoding = Encoding::for_label(meta_charset.as_bytes()).unwrap_or(UTF_8);
-                    let (text, _, _) = encoding.decode(&full);
-                    Ok(text.into_owned())
-                } else {
-                    Ok(text.into_owned())
+        self.with_status_policy(StatusPolicy::default())
+            .html()
+            .await
+    }
+}
+
+/// Response whose status is checked with a [`StatusPolicy`] before it is scraped
+pub struct PolicyResponse {
+    response: Response,
+    policy: StatusPolicy,
+}
+
+impl PolicyResponse {
+    /// Returns the response if its status is accepted by the policy
+    async fn accepted(self) -> Result<Response> {
+        let status = self.response.status();
+        if self.policy.accepts(status) {
+            Ok(self.response)
+        } else {
+            let url = self.response.url().to_string();
+            let headers = self.response.headers().clone();
+            let mut response = self.response;
+            let mut body = Vec::new();
+            // only the beginning of the error body is read, see `StatusPolicy::error_body_limit`
+            let limit = self.policy.body_limit();
+            while body.len() < limit {
+                match response.chunk().await? {
+                    Some(chunk) => body.extend_from_slice(&chunk),
+                    None => break,
                 }
             }
+            body.truncate(limit);
+            Err(self.policy.reject(url, status, headers, &body))
         }
     }
+
+    /// Returns the `Content-Type` and the body of the accepted response
+    async fn accepted_body(self) -> Result<(Option<String>, bytes::Bytes)> {
+        let response = self.accepted().await?;
+        let content_type = content_type(&response);
+        Ok((content_type, response.bytes().await?))
+    }
 }
 
-fn extract_charset(html: &str) -> Option<String> {
-    // 优先匹配 <meta charset="UTF-8"> 或 <meta charset='UTF-8'>
-    let re1 = Regex::new(r#"(?i)<meta\s+[^>]*charset=['"]?([a-zA-Z0-9\-_]+)"#).unwrap();
-    if let Some(cap) = re1.captures(html) {
-        return Some(cap[1].to_string());
+impl ScraperResponse for PolicyResponse {
+    fn with_status_policy(self, policy: StatusPolicy) -> PolicyResponse {
+        PolicyResponse { policy, ..self }
+    }
+
+    #[cfg(feature = "jsonpath")]
+    async fn jsonpath(self) -> Result<Json> {
+        let json = self.accepted().await?.text().await?;
+        Json::new(json.as_str())
     }
 
-    // 兼容 HTML4/XHTML 写法
-    // 匹配 <meta http-equiv="Content-Type" content="text/html; charset=GB2312">
-    // 支持属性乱序、单双引号
-    let re2 = Regex::new(r#"(?i)<meta\s+[^>]*http-equiv=['"]?content-type['"]?[^>]*content=['"][^>]*charset=([a-zA-Z0-9\-_]+)"#).unwrap();
-    if let Some(cap) = re2.captures(html) {
-        return Some(cap[1].to_string());
+    #[cfg(feature = "jmespath")]
+    async fn jmespath(self) -> Result<JmesJson> {
+        let json = self.accepted().await?.text().await?;
+        JmesJson::new(json.as_str())
     }
 
-    None
-}
+    #[cfg(feature = "json_stream")]
+    async fn jsonpath_stream<T: DeserializeOwned>(self, path: &str) -> Result<JsonItemStream<T>> {
+        let path = StreamJsonPath::parse(path)?;
+        let response = self.accepted().await?;
+        Ok(JsonItemStream::new(response.bytes_stream(), path))
+    }
 
-#[cfg(test)]
-mod tests {
-
-    #[test]
-    fn test_extract_charset() {
-        use super::extract_charset;
-        let html = r#"
-        <!DOCTYPE html>
-        <html>
-        <head>
-            <meta charset="gb2312">
-            <title>Example</title>
-        </head>
-        <body><p>Hello, world!</p></body>
-        </html>
-        "#;
-
-        let cs = extract_charset(html);
-        assert!(cs.is_some());
-        assert_eq!(cs.unwrap(), "gb2312");
-
-        let html = r#"
-        <!DOCTYPE html>
-        <html>
-        <head>
-            <meta charset='gb2312'>
-            <title>Example</title>
-        </head>
-        <body><p>Hello, world!</p></body>
-        </html>
-        "#;
-
-        let cs = extract_charset(html);
-        assert!(cs.is_some());
-        assert_eq!(cs.unwrap(), "gb2312");
-
-        let html = r#"
-        <!DOCTYPE html>
-        <html>
-        <head>
-            <meta http-equiv="Content-Type" content="text/html; charset=GB2312">
-            <title>Example</title>
-        </head>
-        <body><p>Hello, world!</p></body>
-        </html>
-        "#;
-
-        let cs = extract_charset(html);
-        assert!(cs.is_some());
-        assert_eq!(cs.unwrap(), "GB2312");
-
-        let html = r#"
-        <!DOCTYPE html>
-        <html>
-        <head>
-            <meta content='text/html; charset=gbk' http-equiv="Content-Type">
-            <title>Example</title>
-        </head>
-        <body><p>Hello, world!</p></body>
-        </html>
-        "#;
-
-        let cs = extract_charset(html);
-        assert!(cs.is_some());
-        assert_eq!(cs.unwrap(), "gbk");
+    #[cfg(feature = "json")]
+    async fn json_with_path_to_err<T: DeserializeOwned>(self) -> Result<T> {
+        let full = self.accepted().await?.bytes().await?;
+        let mut deserializer = serde_json::Deserializer::from_slice(&full);
+        Ok(serde_path_to_error::deserialize(&mut deserializer)?)
     }
+
+    #[cfg(feature = "css_selector")]
+    async fn css_selector(self) -> Result<Html> {
+        let (content_type, full) = self.accepted_body().await?;
+        Ok(Html::from_bytes(&full, content_type.as_deref()))
+    }
+
+    #[cfg(feature = "xpath")]
+    async fn xpath(self) -> Result<XHtml> {
+        let (content_type, full) = self.accepted_body().await?;
+        XHtml::from_bytes(&full, content_type.as_deref())
+    }
+
+    #[cfg(feature = "xpath")]
+    async fn xml(self) -> Result<XHtml> {
+        let (content_type, full) = self.accepted_body().await?;
+        XHtml::from_xml_bytes(&full, content_type.as_deref())
+    }
+
+    #[cfg(feature = "html_stream")]
+    async fn css_selector_stream(self, selector: &str) -> Result<HtmlElementStream> {
+        let selector = StreamSelector::parse(selector)?;
+        let response = self.accepted().await?;
+        let content_type = content_type(&response);
+        Ok(HtmlElementStream::new(
+            response.bytes_stream(),
+            selector,
+            content_type,
+        ))
+    }
+
+    async fn html(self) -> Result<String> {
+        let (content_type, full) = self.accepted_body().await?;
+        Ok(detect_encoding(&full, content_type.as_deref()).decode(&full))
+    }
+}
+
+fn content_type(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
 }
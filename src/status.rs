@@ -0,0 +1,224 @@
+//!  Policy deciding which response status codes are scraped
+//!
+use crate::error::{body_preview, ScraperError};
+use reqwest::{header::HeaderMap, StatusCode};
+use std::collections::HashSet;
+
+/// Converts the body of a rejected response into a typed error
+type ErrorParser = fn(&str, StatusCode, &[u8]) -> Option<ScraperError>;
+
+/// Maximum number of bytes read from the body of a rejected response by default,
+/// see [`StatusPolicy::error_body_limit`]
+pub const ERROR_BODY_LIMIT: usize = 64 * 1024;
+
+/// Decides which response status codes are accepted by [`crate::ScraperResponse`],
+/// and how the body of a rejected response is reported.
+///
+/// By default only `2xx` responses are accepted,
+/// the others fail with [`ScraperError::HttpError`] holding the headers and a preview of the body.
+#[derive(Debug, Clone, Default)]
+pub struct StatusPolicy {
+    accepted: HashSet<StatusCode>,
+    accept_all: bool,
+    error_parser: Option<ErrorParser>,
+    error_body_limit: Option<usize>,
+}
+
+impl StatusPolicy {
+    /// Accept `2xx` responses and the given status codes
+    pub fn accept<I: IntoIterator<Item = StatusCode>>(statuses: I) -> Self {
+        Self::default().and_accept(statuses)
+    }
+
+    /// Accept every response status
+    pub fn accept_all() -> Self {
+        Self {
+            accept_all: true,
+            ..Self::default()
+        }
+    }
+
+    /// Accept the given status codes in addition to the ones already accepted
+    pub fn and_accept<I: IntoIterator<Item = StatusCode>>(mut self, statuses: I) -> Self {
+        self.accepted.extend(statuses);
+        self
+    }
+
+    /// Deserialize the JSON body of rejected responses into `E`,
+    /// which is reported as [`ScraperError::HttpJsonError`].
+    /// If the body can't be deserialized, [`ScraperError::HttpError`] is returned instead.
+    ///
+    /// The whole body of a rejected response is read to deserialize it,
+    /// unless a limit is set with [`StatusPolicy::error_body_limit`].
+    /// A body cut off at the limit usually isn't valid JSON, and is reported as [`ScraperError::HttpError`].
+    #[cfg(feature = "json")]
+    pub fn json_error<E>(mut self) -> Self
+    where
+        E: serde::de::DeserializeOwned + std::fmt::Debug + Send + Sync + 'static,
+    {
+        self.error_parser = Some(parse_json_error::<E>);
+        self
+    }
+
+    /// Read at most `limit` bytes of the body of rejected responses.
+    ///
+    /// By default [`ERROR_BODY_LIMIT`] bytes are read, or the whole body with [`StatusPolicy::json_error`]
+    pub fn error_body_limit(mut self, limit: usize) -> Self {
+        self.error_body_limit = Some(limit);
+        self
+    }
+
+    /// Maximum number of bytes read from the body of a rejected response
+    pub(crate) fn body_limit(&self) -> usize {
+        match (self.error_body_limit, self.error_parser) {
+            (Some(limit), _) => limit,
+            (None, Some(_)) => usize::MAX,
+            (None, None) => ERROR_BODY_LIMIT,
+        }
+    }
+
+    /// Check whether the response status is accepted
+    pub fn accepts(&self, status: StatusCode) -> bool {
+        self.accept_all || status.is_success() || self.accepted.contains(&status)
+    }
+
+    /// Build the error of a rejected response
+    pub(crate) fn reject(
+        &self,
+        url: String,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: &[u8],
+    ) -> ScraperError {
+        self.error_parser
+            .and_then(|parse| parse(&url, status, body))
+            .unwrap_or_else(|| ScraperError::HttpError {
+                url,
+                status,
+                headers: Box::new(headers),
+                body: body_preview(body),
+            })
+    }
+}
+
+#[cfg(feature = "json")]
+fn parse_json_error<E>(url: &str, status: StatusCode, body: &[u8]) -> Option<ScraperError>
+where
+    E: serde::de::DeserializeOwned + std::fmt::Debug + Send + Sync + 'static,
+{
+    let error: E = serde_json::from_slice(body).ok()?;
+    Some(ScraperError::HttpJsonError {
+        url: url.into(),
+        status,
+        message: format!("{:?}", error),
+        error: Box::new(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_status_policy() {
+        use super::*;
+
+        let policy = StatusPolicy::default();
+        assert!(policy.accepts(StatusCode::OK));
+        assert!(!policy.accepts(StatusCode::NOT_FOUND));
+
+        let policy = StatusPolicy::accept([StatusCode::NOT_FOUND, StatusCode::GONE]);
+        assert!(policy.accepts(StatusCode::NO_CONTENT));
+        assert!(policy.accepts(StatusCode::GONE));
+        assert!(!policy.accepts(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(StatusPolicy::accept_all().accepts(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_json_error() {
+        use super::*;
+        use std::collections::HashMap;
+
+        type ApiError = HashMap<String, String>;
+
+        let policy = StatusPolicy::default().json_error::<ApiError>();
+        let error = policy.reject(
+            "http://example.com".into(),
+            StatusCode::BAD_REQUEST,
+            HeaderMap::new(),
+            br#"{"code":"invalid_token"}"#,
+        );
+        let api_error = error.json_error::<ApiError>().unwrap();
+        assert_eq!(api_error["code"], "invalid_token");
+
+        // the error body is read whole to be deserialized, unless it is limited
+        assert_eq!(StatusPolicy::default().body_limit(), ERROR_BODY_LIMIT);
+        assert_eq!(policy.body_limit(), usize::MAX);
+        assert_eq!(policy.clone().error_body_limit(1024).body_limit(), 1024);
+        assert_eq!(
+            StatusPolicy::default().error_body_limit(16).body_limit(),
+            16
+        );
+
+        let error = policy.reject(
+            "http://example.com".into(),
+            StatusCode::BAD_GATEWAY,
+            HeaderMap::new(),
+            b"<html>Bad Gateway</html>",
+        );
+        assert!(matches!(
+            error,
+            ScraperError::HttpError { status: StatusCode::BAD_GATEWAY, ref body, .. }
+                if body == "<html>Bad Gateway</html>"
+        ));
+    }
+
+    #[tokio::test]
+    #[cfg(all(feature = "json", feature = "jsonpath"))]
+    async fn test_large_json_error() {
+        use super::*;
+        use crate::ScraperResponse;
+        use std::collections::HashMap;
+
+        type ApiError = HashMap<String, String>;
+
+        let response = || {
+            let detail = "x".repeat(2 * ERROR_BODY_LIMIT);
+            let body = format!(r#"{{"code":"invalid_token","detail":"{detail}"}}"#);
+            reqwest::Response::from(
+                http::Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(body)
+                    .unwrap(),
+            )
+        };
+
+        // the body larger than the default limit is read whole to be deserialized
+        let error = response()
+            .with_status_policy(StatusPolicy::default().json_error::<ApiError>())
+            .jsonpath()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.json_error::<ApiError>().unwrap()["code"],
+            "invalid_token"
+        );
+
+        let error = response()
+            .with_status_policy(
+                StatusPolicy::default()
+                    .json_error::<ApiError>()
+                    .error_body_limit(1024),
+            )
+            .jsonpath()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ScraperError::HttpError {
+                status: StatusCode::BAD_REQUEST,
+                ..
+            }
+        ));
+    }
+}
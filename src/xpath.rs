@@ -1,14 +1,44 @@
 //!  Evalute the value in HTML response using xpath expression
 //!
-use crate::error::{Result, ScraperError};
+use crate::{
+    charset::detect_encoding,
+    error::{Result, ScraperError},
+    FromXPath,
+};
 use itertools::Itertools;
 use libxml::{
-    tree::Document,
-    xpath::{Context, Object},
+    bindings::{
+        xmlChar, xmlDocPtr, xmlElementType, xmlElementType_XML_ATTRIBUTE_NODE,
+        xmlElementType_XML_CDATA_SECTION_NODE, xmlElementType_XML_COMMENT_NODE,
+        xmlElementType_XML_DOCUMENT_FRAG_NODE, xmlElementType_XML_DOCUMENT_NODE,
+        xmlElementType_XML_ELEMENT_NODE, xmlElementType_XML_HTML_DOCUMENT_NODE,
+        xmlElementType_XML_NAMESPACE_DECL, xmlElementType_XML_PI_NODE,
+        xmlElementType_XML_TEXT_NODE, xmlFree, xmlGetNodePath, xmlGetProp, xmlHasProp,
+        xmlNodeGetContent, xmlNodePtr, xmlXPathCastNodeToString, xmlXPathCastToBoolean,
+        xmlXPathCastToNumber, xmlXPathCastToString, xmlXPathCompExprPtr, xmlXPathCompile,
+        xmlXPathCompiledEval, xmlXPathCompiledEvalToBoolean, xmlXPathContextPtr,
+        xmlXPathFreeCompExpr, xmlXPathFreeContext, xmlXPathFreeObject, xmlXPathNewBoolean,
+        xmlXPathNewContext, xmlXPathNewFloat, xmlXPathNewNodeSet, xmlXPathNewString,
+        xmlXPathNodeSetAdd, xmlXPathObjectPtr, xmlXPathObjectType_XPATH_BOOLEAN,
+        xmlXPathObjectType_XPATH_NODESET, xmlXPathObjectType_XPATH_NUMBER,
+        xmlXPathObjectType_XPATH_STRING, xmlXPathRegisterNs, xmlXPathRegisterVariable,
+    },
+    parser::ParserOptions,
+    tree::{Document, SaveOptions},
+};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    ffi::{c_char, CStr, CString},
+    fmt::{Debug, Display, Formatter},
+    ptr,
+    rc::Rc,
+    slice,
+    str::FromStr,
+    sync::{Arc, Weak},
 };
-use std::collections::HashSet;
 
-/// Html Response
+/// Html or Xml Response
 #[derive(Clone)]
 pub struct XHtml {
     doc: Document,
@@ -16,169 +46,1093 @@ pub struct XHtml {
 
 /// Wrap HTML document and compiled xpath
 pub struct XPathResult {
-    object: Object,
+    object: XPathObject,
+    owner: NodeOwner,
+}
+
+/// Result object of an evaluation, owned by the [`XPathResult`]
+struct XPathObject(xmlXPathObjectPtr);
+
+impl Drop for XPathObject {
+    fn drop(&mut self) {
+        unsafe { xmlXPathFreeObject(self.0) }
+    }
+}
+
+/// Kind of value an xpath expression evaluated to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XPathResultKind {
+    /// Node-set, e.g. `//li` or `//a/@href`
+    NodeSet,
+    /// Boolean, e.g. `boolean(//div[@class='sold-out'])`
+    Boolean,
+    /// Number, e.g. `count(//li)` or `sum(//td/@data-price)`
+    Number,
+    /// String, e.g. `string(//title)`
+    String,
+    /// Other libxml result types, e.g. XPointer locations
+    Other,
 }
 
 impl XHtml {
     /// constructor
     pub fn new<S: Into<String>>(html_str: S) -> Result<Self> {
         let parser = libxml::parser::Parser::default_html();
-        let doc = parser.parse_string(html_str.into())?;
+        // the string is already decoded, the `<meta>` charset must not be applied again
+        let options = ParserOptions {
+            ignore_enc: true,
+            ..ParserOptions::default()
+        };
+        let doc = parser.parse_string_with_options(html_str.into(), options)?;
+        Ok(Self { doc })
+    }
+
+    /// Decode and parse the HTML bytes,
+    /// the encoding is detected from the `Content-Type` header value and the document,
+    /// see [`crate::charset::detect_encoding`]
+    pub fn from_bytes(bytes: &[u8], content_type: Option<&str>) -> Result<Self> {
+        Self::new(detect_encoding(bytes, content_type).decode(bytes))
+    }
+
+    /// Parse a XML document, e.g. a sitemap or a RSS feed, with the strict XML parser,
+    /// malformed XML fails with [`ScraperError::HtmlParseError`].
+    ///
+    /// Elements in a default namespace, e.g. `<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">`,
+    /// are only matched by a prefix registered with [`XPathContext::namespace`] or by `*[local-name()='urlset']`.
+    pub fn from_xml<S: Into<String>>(xml_str: S) -> Result<Self> {
+        let parser = libxml::parser::Parser::default();
+        // the string is already decoded, the encoding of the XML declaration must not be applied again
+        let options = ParserOptions {
+            recover: false,
+            no_net: true,
+            ignore_enc: true,
+            ..ParserOptions::default()
+        };
+        let doc = parser.parse_string_with_options(xml_str.into(), options)?;
         Ok(Self { doc })
     }
+
+    /// Decode and parse the XML bytes with the strict XML parser,
+    /// the encoding is detected from the `Content-Type` header value and the XML declaration,
+    /// see [`crate::charset::detect_encoding`]
+    pub fn from_xml_bytes(bytes: &[u8], content_type: Option<&str>) -> Result<Self> {
+        Self::from_xml(detect_encoding(bytes, content_type).decode(bytes))
+    }
+
     /// Using xpath to extract results from html
-    pub fn select(&self, xpath: &str) -> Result<XPathResult> {
-        let context = Context::new(&self.doc)
-            .map_err(|_| ScraperError::XPathError(format!("xpath parse failed:{}", xpath)))?;
-        let object = context
-            .evaluate(xpath)
-            .map_err(|_| ScraperError::XPathError(format!("xpath parse failed:{}", xpath)))?;
-        Ok(XPathResult { object })
+    pub fn select<X: IntoXPath>(&self, xpath: X) -> Result<XPathResult> {
+        self.context()?.select(xpath)
+    }
+
+    /// Evaluate the xpath as a boolean, e.g. a node-set is true if it is non-empty
+    pub fn matches<X: IntoXPath>(&self, xpath: X) -> Result<bool> {
+        self.context()?.matches(xpath)
+    }
+
+    /// Create an evaluation context to issue many queries against this document
+    pub fn context(&self) -> Result<XPathContext> {
+        XPathContext::new(self.doc.doc_ptr(), NodeOwner::Document(self.doc.clone()))
+    }
+}
+
+/// Compiled xpath expression, reusable across documents.
+///
+/// libxml2 caches state in the compiled steps while evaluating them, e.g. the functions it looked up,
+/// so it is neither `Send` nor `Sync`. Compile it once per thread, e.g. in a `thread_local!`,
+/// and pass it to `select` to avoid re-parsing the expression:
+/// ```
+/// use reqwest_scraper::xpath::{XHtml, XPath};
+///
+/// thread_local! {
+///     static TITLE: XPath = XPath::compile("//h1/text()").unwrap();
+/// }
+///
+/// let html = XHtml::new("<h1>Hello</h1>").unwrap();
+/// let title = TITLE.with(|title| html.select(title).unwrap().as_str());
+/// assert_eq!(title, Some("Hello".into()));
+/// ```
+#[derive(Clone)]
+pub struct XPath {
+    compiled: Rc<CompiledExpr>,
+    xpath: Rc<str>,
+}
+
+struct CompiledExpr(xmlXPathCompExprPtr);
+
+impl Drop for CompiledExpr {
+    fn drop(&mut self) {
+        unsafe { xmlXPathFreeCompExpr(self.0) }
+    }
+}
+
+impl XPath {
+    /// Compile the xpath expression
+    pub fn compile(xpath: &str) -> Result<Self> {
+        let parse_failed = || ScraperError::XPathError(format!("xpath parse failed:{}", xpath));
+        let c_xpath = CString::new(xpath).map_err(|_| parse_failed())?;
+        let ptr = unsafe { xmlXPathCompile(c_xpath.as_ptr() as *const u8) };
+        if ptr.is_null() {
+            return Err(parse_failed());
+        }
+        Ok(Self {
+            compiled: Rc::new(CompiledExpr(ptr)),
+            xpath: xpath.into(),
+        })
+    }
+
+    /// Returns the xpath expression
+    pub fn as_str(&self) -> &str {
+        &self.xpath
+    }
+}
+
+impl Debug for XPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("XPath").field(&self.xpath).finish()
+    }
+}
+
+impl FromStr for XPath {
+    type Err = ScraperError;
+
+    fn from_str(xpath: &str) -> Result<Self> {
+        Self::compile(xpath)
+    }
+}
+
+/// Xpath string or compiled [`XPath`] accepted by `select`, `matches` and the `find*` methods
+pub trait IntoXPath {
+    /// Returns the compiled xpath
+    fn into_xpath(self) -> Result<XPath>;
+}
+
+impl IntoXPath for &str {
+    fn into_xpath(self) -> Result<XPath> {
+        XPath::compile(self)
+    }
+}
+
+impl IntoXPath for &String {
+    fn into_xpath(self) -> Result<XPath> {
+        XPath::compile(self)
+    }
+}
+
+impl IntoXPath for XPath {
+    fn into_xpath(self) -> Result<XPath> {
+        Ok(self)
+    }
+}
+
+impl IntoXPath for &XPath {
+    fn into_xpath(self) -> Result<XPath> {
+        Ok(self.clone())
+    }
+}
+
+/// Reusable xpath evaluation context of a document.
+///
+/// The nodes passed to the `*_from` and `find*` methods must belong to the same document.
+///
+/// `$variables` and namespace prefixes are bound before evaluating,
+/// instead of formatting scraped values into the xpath:
+/// ```
+/// use reqwest_scraper::xpath::XHtml;
+///
+/// let html = XHtml::new(r#"<a title="Tom's page" href="/tom">Tom</a>"#).unwrap();
+/// let mut context = html.context().unwrap().variable("title", "Tom's page").unwrap();
+/// let href = context.select("//a[@title=$title]/@href").unwrap();
+/// assert_eq!(href.as_str(), Some("/tom".into()));
+/// ```
+pub struct XPathContext {
+    ptr: xmlXPathContextPtr,
+    owner: NodeOwner,
+}
+
+impl Drop for XPathContext {
+    fn drop(&mut self) {
+        unsafe { xmlXPathFreeContext(self.ptr) }
+    }
+}
+
+impl XPathContext {
+    fn new(doc: xmlDocPtr, owner: NodeOwner) -> Result<Self> {
+        let ptr = unsafe { xmlXPathNewContext(doc) };
+        if ptr.is_null() {
+            return Err(ScraperError::XPathError(
+                "failed to create xpath context".into(),
+            ));
+        }
+        Ok(Self { ptr, owner })
+    }
+
+    /// Bind the `$name` variable to a string, number, boolean or node-set,
+    /// the nodes must belong to the document of the context
+    pub fn variable<V: Into<XPathValue>>(self, name: &str, value: V) -> Result<Self> {
+        let register_failed =
+            || ScraperError::XPathError(format!("failed to bind xpath variable:{}", name));
+        let c_name = CString::new(name).map_err(|_| register_failed())?;
+        let value = value.into();
+        if let XPathValue::NodeSet(nodes) = &value {
+            for node in nodes {
+                self.check_document(node)?;
+            }
+        }
+        let value = value.into_object_ptr().ok_or_else(register_failed)?;
+        // the context takes the ownership of the value
+        let status =
+            unsafe { xmlXPathRegisterVariable(self.ptr, c_name.as_ptr() as *const u8, value) };
+        if status != 0 {
+            unsafe { xmlXPathFreeObject(value) };
+            return Err(register_failed());
+        }
+        Ok(self)
+    }
+
+    /// Register the namespace prefix, e.g. `svg` for `http://www.w3.org/2000/svg`
+    pub fn namespace(self, prefix: &str, href: &str) -> Result<Self> {
+        let register_failed =
+            || ScraperError::XPathError(format!("failed to register xpath namespace:{}", prefix));
+        let c_prefix = CString::new(prefix).map_err(|_| register_failed())?;
+        let c_href = CString::new(href).map_err(|_| register_failed())?;
+        let status = unsafe {
+            xmlXPathRegisterNs(
+                self.ptr,
+                c_prefix.as_ptr() as *const u8,
+                c_href.as_ptr() as *const u8,
+            )
+        };
+        if status != 0 {
+            return Err(register_failed());
+        }
+        Ok(self)
+    }
+
+    /// Nodes of another document can't be evaluated, the context doesn't keep their document alive
+    fn check_document(&self, node: &Node) -> Result<()> {
+        if unsafe { (*node.ptr).doc != (*self.ptr).doc } {
+            return Err(ScraperError::XPathError(
+                "the node doesn't belong to the document of the xpath context".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Evaluate the compiled xpath relative to the node, or to the document if `node` is `None`
+    fn evaluate(&mut self, xpath: &XPath, node: Option<&Node>) -> Result<XPathResult> {
+        if let Some(node) = node {
+            self.check_document(node)?;
+        }
+        let ptr = unsafe {
+            (*self.ptr).node = node.map_or(ptr::null_mut(), |n| n.ptr);
+            xmlXPathCompiledEval(xpath.compiled.0, self.ptr)
+        };
+        if ptr.is_null() {
+            return Err(ScraperError::XPathError(format!(
+                "xpath evaluate failed:{}",
+                xpath.as_str()
+            )));
+        }
+        Ok(XPathResult {
+            object: XPathObject(ptr),
+            owner: self.owner.clone(),
+        })
+    }
+
+    fn evaluate_boolean(&mut self, xpath: &XPath, node: Option<&Node>) -> Result<bool> {
+        if let Some(node) = node {
+            self.check_document(node)?;
+        }
+        let result = unsafe {
+            (*self.ptr).node = node.map_or(ptr::null_mut(), |n| n.ptr);
+            xmlXPathCompiledEvalToBoolean(xpath.compiled.0, self.ptr)
+        };
+        match result {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ScraperError::XPathError(format!(
+                "xpath evaluate failed:{}",
+                xpath.as_str()
+            ))),
+        }
+    }
+
+    /// Evaluate the xpath against the document
+    pub fn select<X: IntoXPath>(&mut self, xpath: X) -> Result<XPathResult> {
+        self.evaluate(&xpath.into_xpath()?, None)
+    }
+
+    /// Evaluate the xpath against the document as a boolean
+    pub fn matches<X: IntoXPath>(&mut self, xpath: X) -> Result<bool> {
+        self.evaluate_boolean(&xpath.into_xpath()?, None)
+    }
+
+    /// Evaluate the relative xpath based on the node
+    pub fn select_from<X: IntoXPath>(
+        &mut self,
+        node: &Node,
+        relative_xpath: X,
+    ) -> Result<XPathResult> {
+        self.evaluate(&relative_xpath.into_xpath()?, Some(node))
+    }
+
+    /// Evaluate the relative xpath based on the node as a boolean
+    pub fn matches_from<X: IntoXPath>(&mut self, node: &Node, relative_xpath: X) -> Result<bool> {
+        self.evaluate_boolean(&relative_xpath.into_xpath()?, Some(node))
+    }
+
+    /// Find nodes based on the node using a relative xpath
+    pub fn findnodes<X: IntoXPath>(&mut self, node: &Node, relative_xpath: X) -> Result<Vec<Node>> {
+        Ok(self.select_from(node, relative_xpath)?.as_nodes())
+    }
+
+    /// Find values based on the node using a relative xpath
+    pub fn findvalues<X: IntoXPath>(
+        &mut self,
+        node: &Node,
+        relative_xpath: X,
+    ) -> Result<Vec<String>> {
+        Ok(self
+            .select_from(node, relative_xpath)?
+            .as_values()
+            .into_iter()
+            .map(|s| s.trim().to_string())
+            .collect_vec())
+    }
+
+    /// Find first node based on the node using a relative xpath
+    pub fn findnode<X: IntoXPath>(
+        &mut self,
+        node: &Node,
+        relative_xpath: X,
+    ) -> Result<Option<Node>> {
+        Ok(self.select_from(node, relative_xpath)?.as_node())
+    }
+
+    /// Find first value based on the node using a relative xpath, e.g. `./text()` or `count(./li)`
+    pub fn findvalue<X: IntoXPath>(
+        &mut self,
+        node: &Node,
+        relative_xpath: X,
+    ) -> Result<Option<String>> {
+        Ok(self
+            .select_from(node, relative_xpath)?
+            .as_value()
+            .map(|v| v.trim().into()))
+    }
+}
+
+/// Value bound to an xpath `$variable`, see [`XPathContext::variable`]
+pub enum XPathValue {
+    /// String value
+    String(String),
+    /// Number value
+    Number(f64),
+    /// Boolean value
+    Boolean(bool),
+    /// Node-set, binding nodes of another document than the context fails
+    NodeSet(Vec<Node>),
+}
+
+impl XPathValue {
+    fn into_object_ptr(self) -> Option<xmlXPathObjectPtr> {
+        let ptr = match self {
+            Self::String(s) => {
+                let s = CString::new(s).ok()?;
+                unsafe { xmlXPathNewString(s.as_ptr() as *const u8) }
+            }
+            Self::Number(n) => unsafe { xmlXPathNewFloat(n) },
+            Self::Boolean(b) => unsafe { xmlXPathNewBoolean(b.into()) },
+            Self::NodeSet(nodes) => unsafe {
+                let ptr = xmlXPathNewNodeSet(ptr::null_mut());
+                if !ptr.is_null() {
+                    for node in nodes.iter() {
+                        xmlXPathNodeSetAdd((*ptr).nodesetval, node.ptr);
+                    }
+                }
+                ptr
+            },
+        };
+        (!ptr.is_null()).then_some(ptr)
+    }
+}
+
+impl From<&str> for XPathValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.into())
+    }
+}
+
+impl From<String> for XPathValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<f64> for XPathValue {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<i32> for XPathValue {
+    fn from(value: i32) -> Self {
+        Self::Number(value.into())
+    }
+}
+
+impl From<bool> for XPathValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<Node> for XPathValue {
+    fn from(value: Node) -> Self {
+        Self::NodeSet(vec![value])
+    }
+}
+
+impl From<&Node> for XPathValue {
+    fn from(value: &Node) -> Self {
+        Self::NodeSet(vec![value.clone()])
+    }
+}
+
+impl From<Vec<Node>> for XPathValue {
+    fn from(value: Vec<Node>) -> Self {
+        Self::NodeSet(value)
     }
 }
 
 /// Html Node
+#[derive(Clone)]
 pub struct Node {
-    node: libxml::tree::node::Node,
+    ptr: xmlNodePtr,
+    owner: NodeOwner,
+}
+
+/// Keeps the memory of the nodes alive, it is only held
+#[allow(dead_code)]
+#[derive(Clone)]
+enum NodeOwner {
+    /// Document the nodes were selected from
+    Document(Document),
+    /// libxml node passed to [`Node::new`], with the nodes selected from it.
+    /// It only holds a weak reference to its document, which must be kept alive by the caller
+    Node(libxml::tree::node::Node),
 }
 
 impl XPathResult {
+    /// Nodes of the node-set, empty for other kinds of results
+    fn node_ptrs(&self) -> &[xmlNodePtr] {
+        unsafe {
+            let set = (*self.object.0).nodesetval;
+            if set.is_null() || (*set).nodeNr <= 0 || (*set).nodeTab.is_null() {
+                return &[];
+            }
+            slice::from_raw_parts((*set).nodeTab, (*set).nodeNr as usize)
+        }
+    }
+
+    /// Nodes of the node-set that belong to the document,
+    /// namespace nodes are copies freed with the result
+    fn document_nodes(&self) -> impl Iterator<Item = Node> + '_ {
+        self.node_ptrs()
+            .iter()
+            .filter(|ptr| unsafe { (***ptr).type_ } != xmlElementType_XML_NAMESPACE_DECL)
+            .map(|ptr| Node::wrap(*ptr, &self.owner))
+    }
+
     /// return multiple results
     pub fn as_nodes(&self) -> Vec<Node> {
-        self.object
-            .get_nodes_as_vec()
-            .into_iter()
-            .map(Node::new)
-            .collect::<Vec<_>>()
+        self.document_nodes().collect_vec()
     }
 
     /// return multiple results as string
     pub fn as_strs(&self) -> Vec<String> {
-        self.object.get_nodes_as_str()
+        self.node_ptrs()
+            .iter()
+            .map(|ptr| unsafe { take_xml_string(xmlXPathCastNodeToString(*ptr)) })
+            .collect_vec()
     }
 
     /// return first result
     pub fn as_node(&self) -> Option<Node> {
-        self.object
-            .get_nodes_as_vec()
-            .first()
-            .map(|n| Node::new(n.to_owned()))
+        self.document_nodes().next()
     }
 
     /// return first result as string
     pub fn as_str(&self) -> Option<String> {
-        self.object
-            .get_nodes_as_str()
+        self.node_ptrs()
             .first()
-            .map(ToOwned::to_owned)
+            .map(|ptr| unsafe { take_xml_string(xmlXPathCastNodeToString(*ptr)) })
+    }
+
+    /// return first result as string like [`XPathResult::as_str`],
+    /// a number, boolean or string result, e.g. `count(//li)`, is returned as its string value
+    pub fn as_value(&self) -> Option<String> {
+        match self.kind() {
+            XPathResultKind::NodeSet | XPathResultKind::Other => self.as_str(),
+            _ => Some(self.to_string_value()),
+        }
+    }
+
+    /// return multiple results as string like [`XPathResult::as_strs`],
+    /// a number, boolean or string result is returned as its single string value
+    pub fn as_values(&self) -> Vec<String> {
+        match self.kind() {
+            XPathResultKind::NodeSet | XPathResultKind::Other => self.as_strs(),
+            _ => vec![self.to_string_value()],
+        }
+    }
+
+    /// Returns the kind of value the xpath evaluated to
+    #[allow(non_upper_case_globals)]
+    pub fn kind(&self) -> XPathResultKind {
+        match unsafe { (*self.object.0).type_ } {
+            xmlXPathObjectType_XPATH_NODESET => XPathResultKind::NodeSet,
+            xmlXPathObjectType_XPATH_BOOLEAN => XPathResultKind::Boolean,
+            xmlXPathObjectType_XPATH_NUMBER => XPathResultKind::Number,
+            xmlXPathObjectType_XPATH_STRING => XPathResultKind::String,
+            _ => XPathResultKind::Other,
+        }
+    }
+
+    /// Returns the number if the xpath evaluated to a number, e.g. `count(//li)`
+    pub fn as_number(&self) -> Option<f64> {
+        (self.kind() == XPathResultKind::Number).then(|| unsafe { (*self.object.0).floatval })
+    }
+
+    /// Returns the boolean if the xpath evaluated to a boolean, e.g. `boolean(//div[@class='sold-out'])`
+    pub fn as_bool(&self) -> Option<bool> {
+        (self.kind() == XPathResultKind::Boolean).then(|| unsafe { (*self.object.0).boolval != 0 })
+    }
+
+    /// Returns the string if the xpath evaluated to a string, e.g. `string(//title)`.
+    ///
+    /// Unlike [`XPathResult::as_str`], which returns the value of the first node of a node-set.
+    pub fn as_string(&self) -> Option<String> {
+        (self.kind() == XPathResultKind::String).then(|| self.to_string_value())
+    }
+
+    /// Convert the result to a number following the xpath `number()` function,
+    /// `NaN` if it isn't numeric
+    pub fn to_number(&self) -> f64 {
+        unsafe { xmlXPathCastToNumber(self.object.0) }
+    }
+
+    /// Convert the result to a boolean following the xpath `boolean()` function,
+    /// e.g. a node-set is true if it is non-empty
+    pub fn to_bool(&self) -> bool {
+        unsafe { xmlXPathCastToBoolean(self.object.0) != 0 }
+    }
+
+    /// Convert the result to a string following the xpath `string()` function,
+    /// e.g. the value of the first node of a node-set
+    pub fn to_string_value(&self) -> String {
+        unsafe { take_xml_string(xmlXPathCastToString(self.object.0)) }
     }
 }
 
 impl Node {
     /// constructor
     pub fn new(node: libxml::tree::node::Node) -> Self {
-        Self { node }
+        Self {
+            ptr: node.node_ptr(),
+            owner: NodeOwner::Node(node),
+        }
+    }
+
+    fn wrap(ptr: xmlNodePtr, owner: &NodeOwner) -> Self {
+        Self {
+            ptr,
+            owner: owner.clone(),
+        }
+    }
+
+    fn node_type(&self) -> xmlElementType {
+        unsafe { (*self.ptr).type_ }
+    }
+
+    fn is_element(&self) -> bool {
+        self.node_type() == xmlElementType_XML_ELEMENT_NODE
+    }
+
+    fn parent(&self) -> Option<Node> {
+        let parent = unsafe { (*self.ptr).parent };
+        (!parent.is_null()).then(|| Self::wrap(parent, &self.owner))
+    }
+
+    /// All child nodes, including text and comments
+    fn child_nodes(&self) -> impl Iterator<Item = Node> + '_ {
+        let mut child = unsafe { (*self.ptr).children };
+        std::iter::from_fn(move || {
+            if child.is_null() {
+                return None;
+            }
+            let node = Self::wrap(child, &self.owner);
+            child = unsafe { (*child).next };
+            Some(node)
+        })
+    }
+
+    /// Attribute nodes in document order
+    fn attributes(&self) -> impl Iterator<Item = Node> + '_ {
+        let mut attr = if self.is_element() {
+            unsafe { (*self.ptr).properties as xmlNodePtr }
+        } else {
+            ptr::null_mut()
+        };
+        std::iter::from_fn(move || {
+            if attr.is_null() {
+                return None;
+            }
+            let node = Self::wrap(attr, &self.owner);
+            attr = unsafe { (*attr).next };
+            Some(node)
+        })
+    }
+
+    /// Text content of the node and its descendants, untrimmed
+    fn content(&self) -> String {
+        unsafe { take_xml_string(xmlNodeGetContent(self.ptr)) }
     }
 
     /// Returns the element name.
     pub fn name(&self) -> String {
-        self.node.get_name()
+        let name = unsafe { (*self.ptr).name };
+        if name.is_null() {
+            return String::new();
+        }
+        unsafe { CStr::from_ptr(name as *const c_char) }
+            .to_string_lossy()
+            .into_owned()
     }
 
     /// Returns the element ID.
     pub fn id(&self) -> Option<String> {
-        self.node.get_attribute("id").map(|s| s.trim().into())
+        self.attr("id")
     }
 
     /// Returns the element class.
     pub fn classes(&self) -> HashSet<String> {
-        self.node
-            .get_class_names()
-            .into_iter()
-            .filter(|c| !c.is_empty())
-            .collect()
+        self.attr("class")
+            .map(|class| class.split_ascii_whitespace().map(Into::into).collect())
+            .unwrap_or_default()
     }
 
     /// Returns the value of an attribute.
     pub fn attr(&self, attr: &str) -> Option<String> {
-        self.node.get_attribute(attr).map(|s| s.trim().into())
+        let c_attr = CString::new(attr).ok()?;
+        let value = unsafe { xmlGetProp(self.ptr, c_attr.as_ptr() as *const u8) };
+        (!value.is_null()).then(|| unsafe { take_xml_string(value) }.trim().into())
     }
 
     /// Check if the attribute exists
     pub fn has_attr(&self, attr: &str) -> bool {
-        self.node.has_attribute(attr)
+        CString::new(attr).is_ok_and(|c_attr| unsafe {
+            !xmlHasProp(self.ptr, c_attr.as_ptr() as *const u8).is_null()
+        })
     }
 
     /// Returns the text of this element.
     pub fn text(&self) -> String {
-        self.node.get_content().trim().into()
+        self.content().trim().into()
     }
 
     /// Returns the HTML of this element.
     pub fn html(&self) -> String {
-        todo!()
+        let mut html = String::new();
+        serialize_node(self, &mut html);
+        html
     }
 
     /// Returns the inner HTML of this element.
     pub fn inner_html(&self) -> String {
-        todo!()
+        let mut html = String::new();
+        serialize_children(self, &mut html);
+        html
+    }
+
+    /// Path of the node in its document, e.g. `/html/body/ul/li[2]`
+    fn path(&self) -> String {
+        unsafe { take_xml_string(xmlGetNodePath(self.ptr)) }
     }
 
     /// Iterate over all child nodes which are elements
     pub fn children(&self) -> Vec<Node> {
-        self.node
-            .get_child_elements()
-            .into_iter()
-            .map(Node::new)
-            .collect_vec()
+        self.child_nodes().filter(Node::is_element).collect_vec()
+    }
+
+    /// Evaluate the relative xpath as a boolean, e.g. a node-set is true if it is non-empty
+    pub fn matches<X: IntoXPath>(&self, relative_xpath: X) -> Result<bool> {
+        self.context()?.matches_from(self, relative_xpath)
     }
 
     /// Find nodes based on this node using a relative xpath
-    pub fn findnodes(&self, relative_xpath: &str) -> Result<Vec<Node>> {
-        Ok(self
-            .node
-            .findnodes(relative_xpath)
-            .map_err(|_| {
-                ScraperError::XPathError(format!("relative xpath parse failed:{}", relative_xpath))
-            })?
-            .into_iter()
-            .map(Node::new)
-            .collect_vec())
+    pub fn findnodes<X: IntoXPath>(&self, relative_xpath: X) -> Result<Vec<Node>> {
+        self.context()?.findnodes(self, relative_xpath)
     }
 
     /// Find values based on this node using a relative xpath
-    pub fn findvalues(&self, relative_xpath: &str) -> Result<Vec<String>> {
-        match self.node.findvalues(relative_xpath) {
-            Ok(vec) => Ok(vec.into_iter().map(|s| s.trim().to_string()).collect_vec()),
-            Err(_) => Err(ScraperError::XPathError(format!(
-                "relative xpath parse failed:{}",
-                relative_xpath
-            ))),
-        }
+    pub fn findvalues<X: IntoXPath>(&self, relative_xpath: X) -> Result<Vec<String>> {
+        self.context()?.findvalues(self, relative_xpath)
     }
 
     /// Find first node based on this node using a relative xpath
-    pub fn findnode(&self, relative_xpath: &str) -> Result<Option<Node>> {
-        Ok(self
-            .node
-            .findnodes(relative_xpath)
-            .map_err(|_| {
-                ScraperError::XPathError(format!("relative xpath parse failed:{}", relative_xpath))
-            })?
-            .first()
-            .map(|node| Node::new(node.to_owned())))
+    pub fn findnode<X: IntoXPath>(&self, relative_xpath: X) -> Result<Option<Node>> {
+        self.context()?.findnode(self, relative_xpath)
     }
 
     /// Find first value based on this node using a relative xpath
-    pub fn findvalue(&self, relative_xpath: &str) -> Result<Option<String>> {
-        Ok(self
-            .node
-            .findvalues(relative_xpath)
-            .map_err(|_| {
-                ScraperError::XPathError(format!("relative xpath parse failed:{}", relative_xpath))
-            })?
-            .first()
-            .map(|v| v.trim().into()))
+    pub fn findvalue<X: IntoXPath>(&self, relative_xpath: X) -> Result<Option<String>> {
+        self.context()?.findvalue(self, relative_xpath)
+    }
+
+    /// Create an evaluation context of the node's document to issue many queries
+    pub fn context(&self) -> Result<XPathContext> {
+        XPathContext::new(unsafe { (*self.ptr).doc }, self.owner.clone())
+    }
+
+    /// Extract the derived struct relative to this node
+    pub fn extract<T: FromXPath>(&self) -> T::XPathExtractResult {
+        T::from_node(self)
+    }
+}
+
+thread_local! {
+    /// Documents parsed by this thread for [`SendXHtml::to_xhtml`], with their source
+    static PARSED: RefCell<Vec<(Weak<str>, XHtml)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Owned [`XHtml`] that can be moved between threads, e.g. held across `.await`
+/// in a multi-threaded runtime or passed to `spawn_blocking`.
+///
+/// libxml documents and nodes are reference counted without atomics and can't be shared,
+/// so it keeps the serialized document instead. Each thread parses it on its first access
+/// and reuses the parsed [`XHtml`] for the next ones, until every clone of the `SendXHtml` is dropped.
+/// ```
+/// use reqwest_scraper::xpath::{SendXHtml, XHtml};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let html = SendXHtml::new(XHtml::new("<h1>Hello</h1>").unwrap());
+/// let title = tokio::task::spawn_blocking(move || {
+///     html.with(|html| html.select("//h1/text()").unwrap().as_str())
+/// })
+/// .await
+/// .unwrap();
+/// assert_eq!(title.unwrap(), Some("Hello".into()));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SendXHtml {
+    source: Arc<str>,
+    xml: bool,
+}
+
+impl SendXHtml {
+    /// Serialize the parsed document
+    pub fn new(html: XHtml) -> Self {
+        let doc = html.doc.doc_ptr();
+        let xml = unsafe { (*doc).type_ } != xmlElementType_XML_HTML_DOCUMENT_NODE;
+        let source = if xml {
+            html.doc.to_string_with_options(SaveOptions::default())
+        } else {
+            // libxml's html serializer updates the `<meta>` charset of the document
+            Node::wrap(doc as xmlNodePtr, &NodeOwner::Document(html.doc.clone())).html()
+        };
+        Self {
+            source: source.into(),
+            xml,
+        }
+    }
+
+    /// Returns the document parsed by the calling thread, it is only parsed on the first access
+    pub fn to_xhtml(&self) -> Result<XHtml> {
+        PARSED.with(|parsed| {
+            let mut parsed = parsed.borrow_mut();
+            // the documents of dropped sources are released
+            parsed.retain(|(source, _)| source.strong_count() > 0);
+            let cached = parsed
+                .iter()
+                .find(|(source, _)| ptr::addr_eq(source.as_ptr(), Arc::as_ptr(&self.source)));
+            if let Some((_, html)) = cached {
+                return Ok(html.clone());
+            }
+            let html = if self.xml {
+                XHtml::from_xml(&*self.source)?
+            } else {
+                XHtml::new(&*self.source)?
+            };
+            parsed.push((Arc::downgrade(&self.source), html.clone()));
+            Ok(html)
+        })
+    }
+
+    /// Access the document parsed by the calling thread
+    pub fn with<R, F: FnOnce(&XHtml) -> R>(&self, f: F) -> Result<R> {
+        Ok(f(&self.to_xhtml()?))
+    }
+
+    /// Extract the derived struct from the document parsed by the calling thread
+    pub fn extract<T: FromXPath>(&self) -> Result<T::XPathExtractResult> {
+        Ok(T::from_xhtml(self.to_xhtml()?))
+    }
+
+    /// Select the nodes matched by the xpath, each one located again in the documents parsed from it
+    pub fn select_nodes<X: IntoXPath>(&self, xpath: X) -> Result<Vec<SendNode>> {
+        let nodes = self.to_xhtml()?.select(xpath)?.as_nodes();
+        Ok(nodes.iter().map(|node| SendNode::new(self, node)).collect())
+    }
+
+    /// Returns the serialized document
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl From<XHtml> for SendXHtml {
+    fn from(html: XHtml) -> Self {
+        Self::new(html)
+    }
+}
+
+impl Debug for SendXHtml {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendXHtml")
+            .field("xml", &self.xml)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Owned [`Node`] that can be moved between threads, see [`SendXHtml`].
+///
+/// It keeps the document and the path of the node, e.g. `/html/body/ul/li[2]`,
+/// which locates the node in every document parsed from it.
+#[derive(Clone)]
+pub struct SendNode {
+    document: SendXHtml,
+    path: Arc<str>,
+}
+
+impl SendNode {
+    fn new(document: &SendXHtml, node: &Node) -> Self {
+        Self {
+            document: document.clone(),
+            path: node.path().into(),
+        }
+    }
+
+    /// Locate the node in the document parsed by the calling thread
+    pub fn to_node(&self) -> Result<Node> {
+        self.document
+            .to_xhtml()?
+            .select(&*self.path)?
+            .as_node()
+            .ok_or_else(|| {
+                ScraperError::XPathError(format!("node not found in document:{}", self.path))
+            })
+    }
+
+    /// Access the node located in the document parsed by the calling thread
+    pub fn with<R, F: FnOnce(&Node) -> R>(&self, f: F) -> Result<R> {
+        Ok(f(&self.to_node()?))
+    }
+
+    /// Extract the derived struct relative to this node
+    pub fn extract<T: FromXPath>(&self) -> Result<T::XPathExtractResult> {
+        self.with(T::from_node)
+    }
+
+    /// Find nodes based on this node using a relative xpath
+    pub fn findnodes<X: IntoXPath>(&self, relative_xpath: X) -> Result<Vec<SendNode>> {
+        let nodes = self.to_node()?.findnodes(relative_xpath)?;
+        Ok(nodes
+            .iter()
+            .map(|node| SendNode::new(&self.document, node))
+            .collect())
+    }
+
+    /// Returns the path of the node in its document
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the document of the node
+    pub fn document(&self) -> &SendXHtml {
+        &self.document
+    }
+}
+
+impl Debug for SendNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SendNode").field(&self.path).finish()
+    }
+}
+
+/// Parse the value extracted for a field of a `FromXPath` struct.
+///
+/// Used by the code generated from `#[derive(FromXPath)]`.
+#[doc(hidden)]
+pub fn parse_field<T>(
+    struct_name: &'static str,
+    field: &'static str,
+    xpath: &'static str,
+    text: String,
+) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    text.parse::<T>()
+        .map_err(|err| ScraperError::XPathParseError {
+            struct_name,
+            field,
+            xpath,
+            message: err.to_string(),
+            text,
+        })
+}
+
+/// Elements that never have an end tag in HTML serialization
+const VOID_ELEMENTS: [&str; 18] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose text children are serialized without escaping
+const RAW_TEXT_ELEMENTS: [&str; 8] = [
+    "iframe",
+    "noembed",
+    "noframes",
+    "noscript",
+    "plaintext",
+    "script",
+    "style",
+    "xmp",
+];
+
+fn serialize_node(node: &Node, html: &mut String) {
+    #[allow(non_upper_case_globals)]
+    match node.node_type() {
+        xmlElementType_XML_ELEMENT_NODE => {
+            let name = qualified_name(node);
+            html.push('<');
+            html.push_str(&name);
+            for attr in node.attributes() {
+                html.push(' ');
+                html.push_str(&qualified_name(&attr));
+                html.push_str("=\"");
+                escape_into(&attr.content(), true, html);
+                html.push('"');
+            }
+            html.push('>');
+            if VOID_ELEMENTS.contains(&name.as_str()) {
+                return;
+            }
+            serialize_children(node, html);
+            html.push_str("</");
+            html.push_str(&name);
+            html.push('>');
+        }
+        xmlElementType_XML_TEXT_NODE => {
+            if in_raw_text_element(node) {
+                html.push_str(&node.content());
+            } else {
+                escape_into(&node.content(), false, html);
+            }
+        }
+        // the libxml html parser keeps <script> and <style> bodies as CDATA sections
+        xmlElementType_XML_CDATA_SECTION_NODE => {
+            if in_raw_text_element(node) {
+                html.push_str(&node.content());
+            } else {
+                html.push_str("<![CDATA[");
+                html.push_str(&node.content());
+                html.push_str("]]>");
+            }
+        }
+        xmlElementType_XML_COMMENT_NODE => {
+            html.push_str("<!--");
+            html.push_str(&node.content());
+            html.push_str("-->");
+        }
+        xmlElementType_XML_PI_NODE => {
+            html.push_str("<?");
+            html.push_str(&node.name());
+            html.push(' ');
+            html.push_str(&node.content());
+            html.push('>');
+        }
+        xmlElementType_XML_ATTRIBUTE_NODE => {
+            html.push_str(&qualified_name(node));
+            html.push_str("=\"");
+            escape_into(&node.content(), true, html);
+            html.push('"');
+        }
+        xmlElementType_XML_DOCUMENT_NODE
+        | xmlElementType_XML_HTML_DOCUMENT_NODE
+        | xmlElementType_XML_DOCUMENT_FRAG_NODE => serialize_children(node, html),
+        _ => {}
+    }
+}
+
+fn serialize_children(node: &Node, html: &mut String) {
+    for child in node.child_nodes() {
+        serialize_node(&child, html);
+    }
+}
+
+fn in_raw_text_element(node: &Node) -> bool {
+    node.parent()
+        .is_some_and(|p| RAW_TEXT_ELEMENTS.contains(&p.name().as_str()))
+}
+
+/// Name of an element or attribute node with its namespace prefix
+fn qualified_name(node: &Node) -> String {
+    let ns = unsafe { (*node.ptr).ns };
+    let prefix = if ns.is_null() {
+        ptr::null()
+    } else {
+        unsafe { (*ns).prefix }
+    };
+    if prefix.is_null() {
+        return node.name();
+    }
+    let prefix = unsafe { CStr::from_ptr(prefix as *const c_char) }.to_string_lossy();
+    if prefix.is_empty() {
+        node.name()
+    } else {
+        format!("{}:{}", prefix, node.name())
+    }
+}
+
+/// Copy a string allocated by libxml and free it, empty if it is null
+unsafe fn take_xml_string(ptr: *mut xmlChar) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    let string = CStr::from_ptr(ptr as *const c_char)
+        .to_string_lossy()
+        .into_owned();
+    if let Some(free) = xmlFree {
+        free(ptr as *mut std::ffi::c_void);
+    }
+    string
+}
+
+fn escape_into(text: &str, attr_mode: bool, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '\u{a0}' => html.push_str("&nbsp;"),
+            '"' if attr_mode => html.push_str("&quot;"),
+            '<' if !attr_mode => html.push_str("&lt;"),
+            '>' if !attr_mode => html.push_str("&gt;"),
+            c => html.push(c),
+        }
     }
 }
 
@@ -217,5 +1171,391 @@ mod tests {
         let p1 = content.findvalue("./p[position()=1]").unwrap();
         assert!(p1.is_some());
         assert_eq!(p1.unwrap(), "Hello, World!");
+
+        assert!(content.matches("./p").unwrap());
+        assert!(content.matches("@id='content'").unwrap());
+        assert!(!content.matches("./span").unwrap());
+        assert!(xhtml.matches("count(//p) = 2").unwrap());
+
+        let root = Node::new(xhtml.doc.get_root_element().unwrap());
+        assert_eq!(root.name(), "html");
+        assert_eq!(
+            root.findvalue("./body/div/p/text()").unwrap(),
+            Some("Hello, World!".into())
+        );
+    }
+
+    #[test]
+    fn test_node_html() {
+        use super::*;
+        let html_str = r#"
+        <html>
+            <body>
+                <div id="content" class="main" data-x='a"b'>Tom &amp; Jerry<br><!-- note --><img src="a.png"><script>if (a < b) {}</script></div>
+            </body>
+        </html>
+        "#;
+
+        let xhtml = XHtml::new(html_str).expect("parse xhtml failed");
+        let content = xhtml
+            .select("//div[@id='content']")
+            .unwrap()
+            .as_node()
+            .unwrap();
+
+        assert_eq!(
+            content.inner_html(),
+            r#"Tom &amp; Jerry<br><!-- note --><img src="a.png"><script>if (a < b) {}</script>"#
+        );
+        assert_eq!(
+            content.html(),
+            r#"<div id="content" class="main" data-x="a&quot;b">Tom &amp; Jerry<br><!-- note --><img src="a.png"><script>if (a < b) {}</script></div>"#
+        );
+
+        let br = content.findnode("./br").unwrap().unwrap();
+        assert_eq!(br.html(), "<br>");
+        assert_eq!(br.inner_html(), "");
+    }
+
+    #[test]
+    fn test_parse_field() {
+        use super::*;
+
+        let id: i64 = parse_field("Item", "id", "./@data-id", "42".into()).unwrap();
+        assert_eq!(id, 42);
+
+        let err =
+            parse_field::<bool>("Item", "sold_out", "./@data-sold-out", "yes".into()).unwrap_err();
+        assert!(matches!(
+            err,
+            ScraperError::XPathParseError { field, xpath, ref text, .. }
+                if field == "sold_out" && xpath == "./@data-sold-out" && text == "yes"
+        ));
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        use super::*;
+        use encoding_rs::GBK;
+
+        let (bytes, _, _) = GBK.encode(r#"<meta charset="gbk"><p class="title">你好</p>"#);
+        let html = XHtml::from_bytes(&bytes, None).unwrap();
+        assert_eq!(
+            html.select("//p[@class='title']/text()").unwrap().as_str(),
+            Some("你好".into())
+        );
+
+        let (bytes, _, _) = GBK.encode(r#"<p class="title">你好</p>"#);
+        let html = XHtml::from_bytes(&bytes, Some("text/html; charset=GBK")).unwrap();
+        assert_eq!(
+            html.select("//p[@class='title']/text()").unwrap().as_str(),
+            Some("你好".into())
+        );
+    }
+
+    #[test]
+    fn test_compiled_xpath() {
+        use super::*;
+        use static_assertions::assert_not_impl_any;
+
+        assert_not_impl_any!(XPath: Send, Sync);
+
+        let title = XPath::compile("//p[@class='title']/text()").unwrap();
+        let href = XPath::compile("./@href").unwrap();
+        let has_href = XPath::compile("boolean(@href)").unwrap();
+        let first = XHtml::new(r#"<p class="title">first</p><a href="/a">a</a><a>b</a>"#).unwrap();
+        let second = XHtml::new(r#"<p class="title">second</p>"#).unwrap();
+        assert_eq!(first.select(&title).unwrap().as_str(), Some("first".into()));
+        assert_eq!(
+            second.select(&title).unwrap().as_str(),
+            Some("second".into())
+        );
+
+        let mut context = first.context().unwrap();
+        let links = context.select("//a").unwrap().as_nodes();
+        assert_eq!(links.len(), 2);
+        assert_eq!(
+            context.findvalue(&links[0], &href).unwrap(),
+            Some("/a".into())
+        );
+        assert_eq!(context.findvalue(&links[1], &href).unwrap(), None);
+        assert!(context.matches_from(&links[0], &has_href).unwrap());
+        assert!(!context.matches_from(&links[1], &has_href).unwrap());
+        assert!(context.matches("//p[@class='title']").unwrap());
+        assert_eq!(
+            context.select(&title).unwrap().as_str(),
+            Some("first".into())
+        );
+
+        // a node of another document is rejected instead of being evaluated in this context
+        let other = second.select("//p").unwrap().as_node().unwrap();
+        assert!(matches!(
+            context.select_from(&other, &title),
+            Err(ScraperError::XPathError(_))
+        ));
+        assert!(context.matches_from(&other, &has_href).is_err());
+        assert!(context.findnodes(&other, "./text()").is_err());
+        assert!(context.findvalue(&other, "./text()").is_err());
+        assert!(context.findnode(&other, "./text()").is_err());
+
+        assert!(XPath::compile("//p[").is_err());
+        assert!("//p".parse::<XPath>().is_ok());
+    }
+
+    #[test]
+    fn test_typed_result() {
+        use super::*;
+
+        let html = XHtml::new(
+            r#"<title> Shop </title><ul><li data-price="1.5">a</li><li data-price="2">b</li></ul>"#,
+        )
+        .unwrap();
+
+        let count = html.select("count(//li)").unwrap();
+        assert_eq!(count.kind(), XPathResultKind::Number);
+        assert_eq!(count.as_number(), Some(2.0));
+        assert_eq!(count.as_bool(), None);
+        assert_eq!(count.as_str(), None);
+        assert_eq!(count.to_string_value(), "2");
+        assert_eq!(count.as_value(), Some("2".into()));
+        assert_eq!(count.as_values(), ["2"]);
+        let sum = html.select("sum(//li/@data-price)").unwrap();
+        assert_eq!(sum.as_number(), Some(3.5));
+
+        let sold_out = html.select("boolean(//div[@class='sold-out'])").unwrap();
+        assert_eq!(sold_out.kind(), XPathResultKind::Boolean);
+        assert_eq!(sold_out.as_bool(), Some(false));
+        assert_eq!(sold_out.to_number(), 0.0);
+        assert_eq!(sold_out.as_value(), Some("false".into()));
+
+        let title = html.select("string(//title)").unwrap();
+        assert_eq!(title.kind(), XPathResultKind::String);
+        assert_eq!(title.as_string(), Some(" Shop ".into()));
+        assert_eq!(title.as_number(), None);
+        assert!(title.to_number().is_nan());
+        assert!(title.to_bool());
+
+        let items = html.select("//li/@data-price").unwrap();
+        assert_eq!(items.kind(), XPathResultKind::NodeSet);
+        assert_eq!(items.as_string(), None);
+        assert_eq!(items.to_string_value(), "1.5");
+        assert_eq!(items.to_number(), 1.5);
+        assert!(items.to_bool());
+        assert_eq!(items.as_values(), ["1.5", "2"]);
+        assert!(!html.select("//table").unwrap().to_bool());
+        assert_eq!(html.select("//table").unwrap().as_value(), None);
+    }
+
+    #[test]
+    fn test_xpath_variables() {
+        use super::*;
+
+        let html = XHtml::new(
+            r#"<ul><li title="Tom's &quot;page&quot;"><a href="/tom">Tom</a></li><li><a href="/b">B</a></li><li><a href="/c">C</a></li></ul>"#,
+        )
+        .unwrap();
+        let mut context = html
+            .context()
+            .unwrap()
+            .variable("title", r#"Tom's "page""#)
+            .unwrap()
+            .variable("index", 2)
+            .unwrap();
+        assert_eq!(
+            context
+                .select("//li[@title=$title]/a/@href")
+                .unwrap()
+                .as_str(),
+            Some("/tom".into())
+        );
+        assert_eq!(
+            context.select("//li[$index]/a/text()").unwrap().as_str(),
+            Some("B".into())
+        );
+        assert!(context.select("//li[$missing]").is_err());
+
+        let items = html.select("//li").unwrap().as_nodes();
+        let node = &items[2];
+        let mut context = node
+            .context()
+            .unwrap()
+            .variable("items", items[..2].to_vec())
+            .unwrap()
+            .variable("tom", &items[0])
+            .unwrap();
+        assert_eq!(
+            context
+                .select_from(node, "count($items)")
+                .unwrap()
+                .as_number(),
+            Some(2.0)
+        );
+        assert_eq!(
+            context.findvalues(node, "$items/a/text()").unwrap(),
+            ["Tom", "B"]
+        );
+        assert_eq!(
+            context.findvalue(node, "./a/text()").unwrap(),
+            Some("C".into())
+        );
+        assert!(context.matches("count($tom | $items) = 2").unwrap());
+
+        // the context only keeps its own document alive
+        let other = XHtml::new("<ul><li>other</li></ul>").unwrap();
+        let other_items = other.select("//li").unwrap().as_nodes();
+        assert!(matches!(
+            html.context()
+                .unwrap()
+                .variable("items", other_items.clone()),
+            Err(ScraperError::XPathError(_))
+        ));
+        assert!(html
+            .context()
+            .unwrap()
+            .variable("items", vec![items[0].clone(), other_items[0].clone()])
+            .is_err());
+        assert!(other
+            .context()
+            .unwrap()
+            .variable("items", other_items)
+            .is_ok());
+
+        let mut context = html.context().unwrap();
+        assert!(context.select("//svg:rect").is_err());
+        let mut context = context
+            .namespace("svg", "http://www.w3.org/2000/svg")
+            .unwrap();
+        assert_eq!(context.select("//svg:rect").unwrap().as_nodes().len(), 0);
+    }
+
+    #[test]
+    fn test_from_xml() {
+        use super::*;
+        use encoding_rs::GBK;
+
+        let rss = XHtml::from_xml(
+            r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+                <item><title>First</title><link>https://example.com/1</link></item>
+                <item><title>Second</title><link>https://example.com/2</link></item>
+            </channel></rss>"#,
+        )
+        .unwrap();
+        let items = rss.select("//item").unwrap().as_nodes();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[1].findvalue("./title/text()").unwrap(),
+            Some("Second".into())
+        );
+        assert_eq!(
+            rss.select("//link/text()").unwrap().as_strs(),
+            ["https://example.com/1", "https://example.com/2"]
+        );
+        // element names are case sensitive in XML
+        assert!(rss.select("//ITEM").unwrap().as_nodes().is_empty());
+
+        let sitemap = XHtml::from_xml(
+            r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>https://example.com/</loc></url></urlset>"#,
+        )
+        .unwrap();
+        assert!(sitemap.select("//url").unwrap().as_nodes().is_empty());
+        // namespace nodes are copies freed with the result, only their values are returned
+        let namespaces = sitemap.select("/*/namespace::*").unwrap();
+        assert!(namespaces.as_nodes().is_empty());
+        assert!(namespaces
+            .as_strs()
+            .contains(&"http://www.sitemaps.org/schemas/sitemap/0.9".to_string()));
+        let mut context = sitemap
+            .context()
+            .unwrap()
+            .namespace("sm", "http://www.sitemaps.org/schemas/sitemap/0.9")
+            .unwrap();
+        assert_eq!(
+            context.select("//sm:url/sm:loc/text()").unwrap().as_str(),
+            Some("https://example.com/".into())
+        );
+
+        let (bytes, _, _) =
+            GBK.encode(r#"<?xml version="1.0" encoding="GBK"?><feed><title>你好</title></feed>"#);
+        let feed = XHtml::from_xml_bytes(&bytes, None).unwrap();
+        assert_eq!(
+            feed.select("//title/text()").unwrap().as_str(),
+            Some("你好".into())
+        );
+
+        assert!(XHtml::from_xml("<rss><channel></rss>").is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_send_xhtml() {
+        use super::*;
+        use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+        assert_impl_all!(SendXHtml: Send, Sync);
+        assert_impl_all!(SendNode: Send, Sync);
+        assert_not_impl_any!(XHtml: Send, Sync);
+        assert_not_impl_any!(Node: Send, Sync);
+        assert_not_impl_any!(XPathResult: Send, Sync);
+
+        let html = SendXHtml::new(
+            XHtml::new(
+                r#"<ul>
+                    <li><a href="/a">A</a></li>
+                    <!-- b --><li class="b"><a href="/b">B &amp; C</a> tail</li>
+                    <script>if (a < b) {}</script>
+                </ul>"#,
+            )
+            .unwrap(),
+        );
+        let items = html.select_nodes("//li").unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].path(), "/html/body/ul/li[2]");
+
+        let task = tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            let links = items[1].findnodes("./a").unwrap();
+            tokio::task::yield_now().await;
+            links[0].with(|a| (a.attr("href"), a.text())).unwrap()
+        });
+        let blocking = tokio::task::spawn_blocking({
+            let html = html.clone();
+            move || {
+                html.with(|html| html.select("//a/text()").unwrap().as_strs())
+                    .unwrap()
+            }
+        });
+        assert_eq!(task.await.unwrap(), (Some("/b".into()), "B & C".into()));
+        assert_eq!(blocking.await.unwrap(), ["A", "B & C"]);
+
+        let parsed = html.to_xhtml().unwrap();
+        assert_eq!(parsed.select("count(//li)").unwrap().as_number(), Some(2.0));
+        // the document is parsed once per thread and shared by the clones
+        let doc = parsed.doc.doc_ptr();
+        assert_eq!(html.clone().to_xhtml().unwrap().doc.doc_ptr(), doc);
+        let item = html.select_nodes("//li").unwrap()[0].to_node().unwrap();
+        assert_eq!(unsafe { (*item.ptr).doc }, doc);
+        let other_thread = std::thread::spawn({
+            let html = html.clone();
+            move || html.to_xhtml().unwrap().doc.doc_ptr() as usize
+        });
+        assert_ne!(other_thread.join().unwrap(), doc as usize);
+        assert_eq!(
+            parsed.select("//script/text()").unwrap().as_str(),
+            Some("if (a < b) {}".into())
+        );
+        let href = html.select_nodes("//li[@class='b']/a/@href").unwrap();
+        assert_eq!(href[0].with(|href| href.text()).unwrap(), "/b");
+
+        let feed = SendXHtml::new(XHtml::from_xml("<feed><Title>Hi</Title></feed>").unwrap());
+        let title = feed.select_nodes("//Title").unwrap();
+        assert_eq!(title[0].with(|title| title.text()).unwrap(), "Hi");
+        assert!(feed
+            .with(|feed| feed.select("//title").unwrap().as_nodes().is_empty())
+            .unwrap());
+
+        // the parsed documents are released once the sources are dropped
+        drop((html, href, parsed, item, title));
+        feed.to_xhtml().unwrap();
+        assert_eq!(PARSED.with(|parsed| parsed.borrow().len()), 1);
     }
 }
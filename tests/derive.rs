@@ -0,0 +1,762 @@
+//! Extract the derived structs from html and json fixtures
+
+#[cfg(feature = "css_selector")]
+mod css_selector {
+    #[test]
+    fn test_typed_fields() {
+        use reqwest_scraper::{css_selector::Html, error::ScraperError, FromCssSelector};
+
+        #[derive(Debug, FromCssSelector)]
+        #[selector(path = ".product")]
+        struct Product {
+            #[selector(path = ".price", default = "0", text)]
+            price: f64,
+
+            #[selector(path = ".stars", attr = "data-stars")]
+            stars: Option<u32>,
+
+            #[selector(path = "li", text)]
+            sizes: Vec<u8>,
+        }
+
+        let html = Html::new(
+            r#"
+            <div class="product">
+                <span class="price">9.5</span><span class="stars" data-stars="4"></span>
+                <ul><li>38</li><li>40</li></ul>
+            </div>
+            <div class="product"></div>"#,
+        );
+        let products = Product::from_html(html).unwrap();
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].price, 9.5);
+        assert_eq!(products[0].stars, Some(4));
+        assert_eq!(products[0].sizes, [38, 40]);
+        // the default text is parsed like the extracted text
+        assert_eq!(products[1].price, 0.0);
+        assert_eq!(products[1].stars, None);
+        assert!(products[1].sizes.is_empty());
+
+        let html = Html::new(r#"<div class="product"><span class="price">free</span></div>"#);
+        match Product::from_html(html) {
+            Err(ScraperError::CssSelectorParseError {
+                struct_name,
+                field,
+                selector,
+                text,
+                ..
+            }) => {
+                assert_eq!(struct_name, "Product");
+                assert_eq!(field, "price");
+                assert_eq!(selector, ".price");
+                assert_eq!(text, "free");
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_nested() {
+        use reqwest_scraper::{css_selector::Html, error::ScraperError, FromCssSelector};
+
+        #[derive(Debug, FromCssSelector)]
+        #[selector(path = ".post")]
+        struct Post {
+            #[selector(path = "h1", default = "", text)]
+            title: String,
+
+            #[selector(path = ".author", nested)]
+            author: Author,
+
+            #[selector(path = ".editor", nested)]
+            editor: Option<Author>,
+
+            #[selector(path = ".comment", nested)]
+            comments: Vec<Comment>,
+        }
+
+        #[derive(Debug, FromCssSelector)]
+        struct Author {
+            #[selector(path = ".name", default = "", text)]
+            name: String,
+        }
+
+        #[derive(Debug, FromCssSelector)]
+        struct Comment {
+            #[selector(path = ".name", default = "", text)]
+            author: String,
+
+            #[selector(path = ".likes", default = "0", text)]
+            likes: u32,
+        }
+
+        let html = Html::new(
+            r#"
+            <div class="post">
+                <h1>Hello</h1>
+                <div class="author"><span class="name">alice</span></div>
+                <p class="comment"><span class="name">bob</span><span class="likes">3</span></p>
+                <p class="comment"><span class="name">carol</span></p>
+            </div>"#,
+        );
+        let posts = Post::from_html(html).unwrap();
+        assert_eq!(posts.len(), 1);
+        let post = &posts[0];
+        assert_eq!(post.title, "Hello");
+        // the nested fields are selected relative to their element, not the document
+        assert_eq!(post.author.name, "alice");
+        assert!(post.editor.is_none());
+        assert_eq!(post.comments.len(), 2);
+        assert_eq!(post.comments[0].author, "bob");
+        assert_eq!(post.comments[0].likes, 3);
+        assert_eq!(post.comments[1].author, "carol");
+        assert_eq!(post.comments[1].likes, 0);
+
+        let html = Html::new(r#"<div class="author"><span class="name">alice</span></div>"#);
+        let author = html.root_element().extract::<Author>().unwrap();
+        assert_eq!(author.name, "alice");
+
+        let html = Html::new(r#"<div class="post"><h1>Hello</h1></div>"#);
+        assert!(matches!(
+            Post::from_html(html),
+            Err(ScraperError::CssSelectorMatchError(message)) if message.contains(".author")
+        ));
+    }
+
+    #[test]
+    fn test_compiled_selectors() {
+        use reqwest_scraper::{
+            css_selector::{Html, Selector},
+            FromCssSelector,
+        };
+        use std::sync::LazyLock;
+
+        static PRODUCT: LazyLock<Selector> = LazyLock::new(|| Selector::parse(".product").unwrap());
+        static PRICE: LazyLock<Selector> = LazyLock::new(|| Selector::parse(".price").unwrap());
+
+        #[derive(Debug, FromCssSelector)]
+        #[selector(path = ".product")]
+        struct Product {
+            #[selector(path = ".price", default = "0", text)]
+            price: u32,
+        }
+
+        let html = Html::new(
+            r#"<div class="product"><span class="price">3</span></div>
+               <div class="product"><span class="price">5</span></div>"#,
+        );
+        let prices = html
+            .select(&*PRODUCT)
+            .unwrap()
+            .iter()
+            .map(|product| product.select(&*PRICE).unwrap().first().unwrap().text())
+            .collect::<Vec<_>>();
+        assert_eq!(prices, ["3", "5"]);
+
+        // the selectors compiled once by the derive are shared between threads
+        std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|i| {
+                    scope.spawn(move || {
+                        let html = Html::new(&format!(
+                            r#"<div class="product"><span class="price">{i}</span></div>"#
+                        ));
+                        Product::from_html(html).unwrap()[0].price
+                    })
+                })
+                .collect::<Vec<_>>();
+            let prices = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(prices, [0, 1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn test_enum() {
+        use reqwest_scraper::{css_selector::Html, FromCssSelector};
+
+        #[derive(Debug, PartialEq, FromCssSelector)]
+        #[selector(path = ".feed > .item")]
+        enum FeedItem {
+            #[selector(when = ".video-badge")]
+            Video {
+                #[selector(path = "video", attr = "src")]
+                src: Option<String>,
+            },
+
+            #[selector(when = ".ad")]
+            Ad,
+
+            Article {
+                #[selector(path = "h2", default = "", text)]
+                title: String,
+            },
+        }
+
+        let html = Html::new(
+            r#"
+            <div class="feed">
+                <div class="item"><h2>news</h2><span class="video-badge"></span><video src="a.mp4"></video></div>
+                <div class="item ad"><h2>buy</h2></div>
+                <div class="item"><h2>news</h2></div>
+            </div>"#,
+        );
+        // `when` matches the item itself as well as its descendants,
+        // the first matching variant wins and the variant without `when` matches the rest
+        assert_eq!(
+            FeedItem::from_html(html).unwrap(),
+            [
+                FeedItem::Video {
+                    src: Some("a.mp4".into())
+                },
+                FeedItem::Ad,
+                FeedItem::Article {
+                    title: "news".into()
+                },
+            ]
+        );
+    }
+
+    const FEED: &str = r#"
+        <div class="feed">
+            <div class="item"><span class="video-badge"></span><video src="a.mp4"></video></div>
+            <div class="item"><h2>poll</h2></div>
+            <div class="item"><span class="audio-badge"></span><audio src="b.mp3"></audio></div>
+        </div>"#;
+
+    #[test]
+    fn test_enum_unmatched() {
+        use reqwest_scraper::{css_selector::Html, error::ScraperError, FromCssSelector};
+
+        #[derive(Debug, PartialEq, FromCssSelector)]
+        #[selector(path = ".feed > .item")]
+        enum Media {
+            #[selector(when = ".video-badge")]
+            Video {
+                #[selector(path = "video", attr = "src")]
+                src: Option<String>,
+            },
+
+            #[selector(when = ".audio-badge")]
+            Audio {
+                #[selector(path = "audio", attr = "src")]
+                src: Option<String>,
+            },
+        }
+
+        // the poll matches no variant and fails the whole list
+        assert!(matches!(
+            Media::from_html(Html::new(FEED)),
+            Err(ScraperError::CssSelectorMatchError(message)) if message.contains("Media")
+        ));
+    }
+
+    #[test]
+    fn test_enum_skip_unmatched() {
+        use reqwest_scraper::{css_selector::Html, FromCssSelector};
+
+        #[derive(Debug, PartialEq, FromCssSelector)]
+        #[selector(path = ".feed > .item", skip_unmatched)]
+        enum Media {
+            #[selector(when = ".video-badge")]
+            Video {
+                #[selector(path = "video", attr = "src")]
+                src: Option<String>,
+            },
+
+            #[selector(when = ".audio-badge")]
+            Audio {
+                #[selector(path = "audio", attr = "src")]
+                src: Option<String>,
+            },
+        }
+
+        let media = Media::from_html(Html::new(FEED)).unwrap();
+        assert_eq!(
+            media,
+            [
+                Media::Video {
+                    src: Some("a.mp4".into())
+                },
+                Media::Audio {
+                    src: Some("b.mp3".into())
+                },
+            ]
+        );
+    }
+}
+
+#[cfg(feature = "xpath")]
+mod xpath {
+    #[test]
+    fn test_typed_fields() {
+        use reqwest_scraper::{error::ScraperError, xpath::XHtml, FromXPath};
+
+        #[derive(Debug, FromXPath)]
+        #[xpath(path = "//div[@class='product']")]
+        struct Product {
+            #[xpath(path = "./span[@class='id']/text()", default = "0")]
+            id: i64,
+
+            #[xpath(path = "./span[@class='price']/text()")]
+            price: Option<f64>,
+
+            #[xpath(path = "./@data-sold-out", default = "false")]
+            sold_out: bool,
+
+            #[xpath(path = "./ul/li/text()")]
+            sizes: Vec<u32>,
+        }
+
+        let html = XHtml::new(
+            r#"
+            <div class="product" data-sold-out="true">
+                <span class="id">7</span><span class="price">9.5</span>
+                <ul><li>38</li><li>40</li></ul>
+            </div>
+            <div class="product"></div>"#,
+        )
+        .unwrap();
+        let products = Product::from_xhtml(html).unwrap();
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].id, 7);
+        assert_eq!(products[0].price, Some(9.5));
+        assert!(products[0].sold_out);
+        assert_eq!(products[0].sizes, [38, 40]);
+        // the default text is parsed like the extracted text
+        assert_eq!(products[1].id, 0);
+        assert_eq!(products[1].price, None);
+        assert!(!products[1].sold_out);
+        assert!(products[1].sizes.is_empty());
+
+        let html =
+            XHtml::new(r#"<div class="product"><ul><li>38</li><li>XL</li></ul></div>"#).unwrap();
+        match Product::from_xhtml(html) {
+            Err(ScraperError::XPathParseError {
+                struct_name,
+                field,
+                xpath,
+                text,
+                ..
+            }) => {
+                assert_eq!(struct_name, "Product");
+                assert_eq!(field, "sizes");
+                assert_eq!(xpath, "./ul/li/text()");
+                assert_eq!(text, "XL");
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_scalar_xpaths() {
+        use reqwest_scraper::{xpath::XHtml, FromXPath};
+
+        #[derive(Debug, FromXPath)]
+        struct Page {
+            #[xpath(path = "string(//title)")]
+            title: Option<String>,
+
+            #[xpath(path = "count(//div[@class='product'])", default = "0")]
+            products: i64,
+
+            #[xpath(path = "sum(//span[@class='price'])", default = "0")]
+            total: f64,
+
+            #[xpath(path = "boolean(//div[@data-sold-out])", default = "false")]
+            has_sold_out: bool,
+        }
+
+        #[derive(Debug, FromXPath)]
+        #[xpath(path = "//div[@class='product']")]
+        struct Product {
+            #[xpath(path = "count(./ul/li)", default = "0")]
+            sizes: u32,
+
+            #[xpath(path = "boolean(@data-sold-out)", default = "false")]
+            sold_out: bool,
+
+            #[xpath(path = "concat(./span[@class='price'], ' EUR')", default = "")]
+            price: String,
+        }
+
+        let html = r#"
+            <html><head><title>Shoes</title></head><body>
+            <div class="product" data-sold-out="true">
+                <span class="price">9.5</span><ul><li>38</li><li>40</li></ul>
+            </div>
+            <div class="product"><span class="price">12</span><ul><li>42</li></ul></div>
+            </body></html>"#;
+        let page = Page::from_xhtml(XHtml::new(html).unwrap()).unwrap();
+        assert_eq!(page.title.as_deref(), Some("Shoes"));
+        assert_eq!(page.products, 2);
+        assert_eq!(page.total, 21.5);
+        assert!(page.has_sold_out);
+
+        let products = Product::from_xhtml(XHtml::new(html).unwrap()).unwrap();
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].sizes, 2);
+        assert!(products[0].sold_out);
+        assert_eq!(products[0].price, "9.5 EUR");
+        assert_eq!(products[1].sizes, 1);
+        assert!(!products[1].sold_out);
+        assert_eq!(products[1].price, "12 EUR");
+    }
+
+    #[test]
+    fn test_nested() {
+        use reqwest_scraper::{error::ScraperError, xpath::XHtml, FromXPath};
+
+        #[derive(Debug, FromXPath)]
+        #[xpath(path = "//div[@class='post']")]
+        struct Post {
+            #[xpath(path = "./h1/text()", default = "")]
+            title: String,
+
+            #[xpath(path = "./div[@class='author']", nested)]
+            author: Author,
+
+            #[xpath(path = "./div[@class='editor']", nested)]
+            editor: Option<Author>,
+
+            #[xpath(path = "./p[@class='comment']", nested)]
+            comments: Vec<Comment>,
+        }
+
+        #[derive(Debug, FromXPath)]
+        struct Author {
+            #[xpath(path = "./span[@class='name']/text()", default = "")]
+            name: String,
+        }
+
+        #[derive(Debug, FromXPath)]
+        struct Comment {
+            #[xpath(path = "./span[@class='name']/text()", default = "")]
+            author: String,
+
+            #[xpath(path = "./span[@class='likes']/text()", default = "0")]
+            likes: u32,
+        }
+
+        let html = XHtml::new(
+            r#"
+            <div class="post">
+                <h1>Hello</h1>
+                <div class="author"><span class="name">alice</span></div>
+                <p class="comment"><span class="name">bob</span><span class="likes">3</span></p>
+                <p class="comment"><span class="name">carol</span></p>
+            </div>"#,
+        )
+        .unwrap();
+        let posts = Post::from_xhtml(html).unwrap();
+        assert_eq!(posts.len(), 1);
+        let post = &posts[0];
+        assert_eq!(post.title, "Hello");
+        // the nested fields are evaluated relative to their node, not the document
+        assert_eq!(post.author.name, "alice");
+        assert!(post.editor.is_none());
+        assert_eq!(post.comments.len(), 2);
+        assert_eq!(post.comments[0].author, "bob");
+        assert_eq!(post.comments[0].likes, 3);
+        assert_eq!(post.comments[1].author, "carol");
+        assert_eq!(post.comments[1].likes, 0);
+
+        let html =
+            XHtml::new(r#"<div class="author"><span class="name">alice</span></div>"#).unwrap();
+        let node = html
+            .select("//div[@class='author']")
+            .unwrap()
+            .as_node()
+            .unwrap();
+        assert_eq!(Author::from_node(&node).unwrap().name, "alice");
+
+        let html = XHtml::new(r#"<div class="post"><h1>Hello</h1></div>"#).unwrap();
+        assert!(matches!(
+            Post::from_xhtml(html),
+            Err(ScraperError::XPathError(message)) if message.contains("div[@class='author']")
+        ));
+    }
+
+    #[test]
+    fn test_compiled_xpaths() {
+        use reqwest_scraper::{xpath::XHtml, FromXPath};
+
+        #[derive(Debug, FromXPath)]
+        #[xpath(path = "//div[@class='product']")]
+        struct Product {
+            #[xpath(path = "./span[@class='price']/text()", default = "0")]
+            price: u32,
+        }
+
+        // each thread compiles the xpaths of the derive once and reuses them
+        std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|i| {
+                    scope.spawn(move || {
+                        (0..2)
+                            .map(|j| {
+                                let html = XHtml::new(format!(
+                                    r#"<div class="product"><span class="price">{}</span></div>"#,
+                                    i * 10 + j
+                                ))
+                                .unwrap();
+                                Product::from_xhtml(html).unwrap()[0].price
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            let prices = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(prices, [[0, 1], [10, 11], [20, 21], [30, 31]]);
+        });
+    }
+
+    #[test]
+    fn test_enum() {
+        use reqwest_scraper::{xpath::XHtml, FromXPath};
+
+        #[derive(Debug, PartialEq, FromXPath)]
+        #[xpath(path = "//div[@class='feed']/div")]
+        enum FeedItem {
+            #[xpath(when = ".//span[@class='video-badge']")]
+            Video {
+                #[xpath(path = "./video/@src")]
+                src: Option<String>,
+            },
+
+            #[xpath(when = "@data-type='ad'")]
+            Ad,
+
+            Article {
+                #[xpath(path = "./h2/text()", default = "")]
+                title: String,
+            },
+        }
+
+        let html = XHtml::new(
+            r#"
+            <div class="feed">
+                <div><h2>news</h2><span class="video-badge"></span><video src="a.mp4"></video></div>
+                <div data-type="ad"><h2>buy</h2></div>
+                <div><h2>news</h2></div>
+            </div>"#,
+        )
+        .unwrap();
+        // the first variant whose `when` is true wins and the variant without `when` matches the rest
+        assert_eq!(
+            FeedItem::from_xhtml(html).unwrap(),
+            [
+                FeedItem::Video {
+                    src: Some("a.mp4".into())
+                },
+                FeedItem::Ad,
+                FeedItem::Article {
+                    title: "news".into()
+                },
+            ]
+        );
+    }
+
+    const FEED: &str = r#"
+        <div class="feed">
+            <div data-type="video"><video src="a.mp4"></video></div>
+            <div data-type="poll"><h2>poll</h2></div>
+            <div data-type="audio"><audio src="b.mp3"></audio></div>
+        </div>"#;
+
+    #[test]
+    fn test_enum_unmatched() {
+        use reqwest_scraper::{error::ScraperError, xpath::XHtml, FromXPath};
+
+        #[derive(Debug, PartialEq, FromXPath)]
+        #[xpath(path = "//div[@class='feed']/div")]
+        enum Media {
+            #[xpath(when = "@data-type='video'")]
+            Video {
+                #[xpath(path = "./video/@src")]
+                src: Option<String>,
+            },
+
+            #[xpath(when = "@data-type='audio'")]
+            Audio {
+                #[xpath(path = "./audio/@src")]
+                src: Option<String>,
+            },
+        }
+
+        // the poll matches no variant and fails the whole list
+        assert!(matches!(
+            Media::from_xhtml(XHtml::new(FEED).unwrap()),
+            Err(ScraperError::XPathError(message)) if message.contains("Media")
+        ));
+    }
+
+    #[test]
+    fn test_enum_skip_unmatched() {
+        use reqwest_scraper::{xpath::XHtml, FromXPath};
+
+        #[derive(Debug, PartialEq, FromXPath)]
+        #[xpath(path = "//div[@class='feed']/div", skip_unmatched)]
+        enum Media {
+            #[xpath(when = "@data-type='video'")]
+            Video {
+                #[xpath(path = "./video/@src")]
+                src: Option<String>,
+            },
+
+            #[xpath(when = "@data-type='audio'")]
+            Audio {
+                #[xpath(path = "./audio/@src")]
+                src: Option<String>,
+            },
+        }
+
+        let media = Media::from_xhtml(XHtml::new(FEED).unwrap()).unwrap();
+        assert_eq!(
+            media,
+            [
+                Media::Video {
+                    src: Some("a.mp4".into())
+                },
+                Media::Audio {
+                    src: Some("b.mp3".into())
+                },
+            ]
+        );
+    }
+}
+
+#[cfg(feature = "jsonpath")]
+mod jsonpath {
+    #[test]
+    fn test_extract() {
+        use reqwest_scraper::{error::ScraperError, jsonpath::Json, FromJsonPath};
+
+        #[derive(Debug, FromJsonPath)]
+        #[jsonpath(path = "$.items[*]")]
+        struct Repo {
+            #[jsonpath(path = "$.full_name", default = "<unname>")]
+            name: String,
+
+            #[jsonpath(path = "$.stargazers_count", default = "0")]
+            stars: u64,
+
+            #[jsonpath(path = "$.topics[*]")]
+            topics: Vec<String>,
+
+            #[jsonpath(path = "$.owner", nested)]
+            owner: Owner,
+
+            #[jsonpath(path = "$.license", nested)]
+            license: Option<License>,
+        }
+
+        #[derive(Debug, FromJsonPath)]
+        struct Owner {
+            #[jsonpath(path = "$.login")]
+            login: Option<String>,
+        }
+
+        #[derive(Debug, FromJsonPath)]
+        struct License {
+            #[jsonpath(path = "$.key", default = "")]
+            key: String,
+        }
+
+        let json = Json::new(
+            r#"{"items": [
+                {"full_name": "a/b", "stargazers_count": 3, "topics": ["x", "y"],
+                 "owner": {"login": "a"}, "license": {"key": "mit"}},
+                {"owner": {}}
+            ]}"#,
+        )
+        .unwrap();
+        let repos = Repo::from_json(json).unwrap();
+        assert_eq!(repos.len(), 2);
+        assert_eq!(repos[0].name, "a/b");
+        assert_eq!(repos[0].stars, 3);
+        assert_eq!(repos[0].topics, ["x", "y"]);
+        assert_eq!(repos[0].owner.login.as_deref(), Some("a"));
+        assert_eq!(repos[0].license.as_ref().unwrap().key, "mit");
+        // the defaults are json text, or a plain string
+        assert_eq!(repos[1].name, "<unname>");
+        assert_eq!(repos[1].stars, 0);
+        assert!(repos[1].topics.is_empty());
+        assert_eq!(repos[1].owner.login, None);
+        assert!(repos[1].license.is_none());
+
+        let json = Json::new(r#"{"items": [{"stargazers_count": "many", "owner": {}}]}"#).unwrap();
+        match Repo::from_json(json) {
+            Err(ScraperError::JsonPathParseError {
+                struct_name,
+                field,
+                path,
+                value,
+                ..
+            }) => {
+                assert_eq!(struct_name, "Repo");
+                assert_eq!(field, "stars");
+                assert_eq!(path, "$.stargazers_count");
+                assert_eq!(value, r#""many""#);
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+
+        let json = Json::new(r#"{"items": [{"full_name": "a/b"}]}"#).unwrap();
+        assert!(matches!(
+            Repo::from_json(json),
+            Err(ScraperError::JsonPathNotFound { path }) if path == "$.owner"
+        ));
+    }
+
+    #[cfg(feature = "jsonpath_rfc9535")]
+    #[test]
+    fn test_backend() {
+        use reqwest_scraper::{
+            error::ScraperError,
+            jsonpath::{Json, JsonPathBackend},
+            FromJsonPath,
+        };
+
+        #[derive(Debug, FromJsonPath)]
+        struct Expensive {
+            #[jsonpath(path = "$.items[?@.price > 10].name")]
+            names: Vec<String>,
+
+            #[jsonpath(path = "$.items[?@.price > 10]", nested)]
+            items: Vec<Item>,
+        }
+
+        #[derive(Debug, FromJsonPath)]
+        struct Item {
+            #[jsonpath(path = "$.tags[?@ != 'sale']")]
+            tags: Vec<String>,
+        }
+
+        let json = Json::new(
+            r#"{"items": [
+                {"name": "pen", "price": 8, "tags": ["office"]},
+                {"name": "lamp", "price": 12, "tags": ["home", "sale"]}
+            ]}"#,
+        )
+        .unwrap();
+        // the filters are RFC 9535 syntax, rejected by the default backend
+        assert!(matches!(
+            json.extract::<Expensive>(),
+            Err(ScraperError::JsonPathError(_))
+        ));
+
+        let json = json.with_backend(JsonPathBackend::Rfc9535);
+        let expensive = json.extract::<Expensive>().unwrap();
+        assert_eq!(expensive.names, ["lamp"]);
+        assert_eq!(expensive.items[0].tags, ["home"]);
+
+        let expensive = Expensive::from_json(json).unwrap();
+        assert_eq!(expensive.names, ["lamp"]);
+    }
+}